@@ -0,0 +1,137 @@
+//! Throughput of the ChucK transforms and of formatting many files with the CLI.
+//!
+//! Run with `cargo bench`. Each group is followed by a few lines on what its numbers mean.
+
+use chuckfmt::{Config, apply_transforms, process_string, resolve_clang_format};
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+use std::fs;
+use std::hint::black_box;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+/// Number of files in the multi-file measurements.
+const FILES: usize = 200;
+
+/// ChucK source built from the idempotence fixtures, repeated to about `bytes` bytes.
+fn source(bytes: usize) -> String {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/idempotence");
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    paths.sort();
+    // the @import lines have to stay at the top to keep their meaning
+    let snippet: String = paths
+        .iter()
+        .map(|p| {
+            fs::read_to_string(p)
+                .unwrap()
+                .replace("@import \"lib.ck\"\n", "")
+        })
+        .collect();
+    let mut out = String::from("@import \"lib.ck\"\n");
+    while out.len() < bytes {
+        out.push_str(&snippet);
+    }
+    out
+}
+
+// -------------------- single file, in process --------------------
+
+fn single_file(c: &mut Criterion) {
+    let mut group = c.benchmark_group("single file");
+    let input = source(1 << 20);
+    let config = Config::default();
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("transforms, 1 MiB", |b| {
+        b.iter(|| process_string(None, &[], None, &config, black_box(&input)).unwrap())
+    });
+
+    let small = "SinOsc s = > dac;\n1 :: second => now;\n";
+    group.throughput(Throughput::Bytes(small.len() as u64));
+    group.bench_function("transforms, 2 lines", |b| {
+        b.iter(|| apply_transforms(black_box(small)))
+    });
+    group.finish();
+
+    println!("  -> the ChucK transforms alone; a typical file is a few KiB, so this is never the");
+    println!("     bottleneck next to starting clang-format. The regexes are compiled once per");
+    println!("     process (tests/regex_once.rs checks it), so the 2-line time is matching only");
+}
+
+// -------------------- many files, through the CLI --------------------
+
+fn many_files(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("chuckfmt-bench-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let file_source = source(4 << 10);
+    let reset = || {
+        for i in 0..FILES {
+            fs::write(dir.join(format!("f{i}.ck")), &file_source).unwrap();
+        }
+    };
+    let run = |args: &[&str]| {
+        let status = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+            .args(args)
+            .args(["--quiet", "-i"])
+            .arg(&dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+
+    let mut group = c.benchmark_group(format!("{FILES} files"));
+    group
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(10))
+        .throughput(Throughput::Elements(FILES as u64));
+    let mut bench = |name: &str, args: &[&str]| {
+        group.bench_function(name, |b| {
+            b.iter_batched(reset, |()| run(args), BatchSize::PerIteration)
+        });
+    };
+
+    bench("no clang-format", &["--no-clang-format"]);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        // a stand-in that starts a process per file but does no formatting work
+        let cat = dir.join("cat-format");
+        fs::write(
+            &cat,
+            "#!/bin/sh\n[ \"$1\" = --version ] && echo 'clang-format version 18.1.3' && exit\nexec cat\n",
+        )
+        .unwrap();
+        fs::set_permissions(&cat, fs::Permissions::from_mode(0o755)).unwrap();
+        bench(
+            "process per file",
+            &["--clang-format-path", cat.to_str().unwrap()],
+        );
+    }
+
+    let clang = resolve_clang_format(None);
+    match &clang {
+        Ok(clang) => bench(
+            "clang-format",
+            &["--clang-format-path", clang.to_str().unwrap()],
+        ),
+        Err(_) => println!("{FILES} files/clang-format: skipped, none installed"),
+    }
+    group.finish();
+
+    println!("  -> no clang-format: reading, transforming and writing back each file, spread over");
+    println!("     all CPUs. The gap up to process per file is the cost of starting a process for");
+    println!("     each file, and what's left above that for clang-format is its own work");
+    println!("  -> clang-format formats exactly one input per run (stdin is read to EOF), so it");
+    println!("     can't be kept running across files; files are formatted in parallel instead");
+    println!("     (see --jobs)");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+criterion_group!(benches, single_file, many_files);
+criterion_main!(benches);
@@ -0,0 +1,526 @@
+//! Project configuration read from `.chuckfmt.toml`.
+//!
+//! Only the small subset of TOML that the config needs is understood: `[table]` and
+//! `[[array-of-tables]]` headers, `key = value` pairs with booleans, integers, strings and
+//! single-line arrays, and `#` comments.
+
+use crate::{FmtError, TRANSFORM_NAMES};
+use lazy_regex::Regex;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the config file looked up next to (and above) each formatted file.
+pub const CONFIG_FILE_NAME: &str = ".chuckfmt.toml";
+
+/// Formatter settings, usually loaded from the nearest `.chuckfmt.toml`.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Post-formatting transforms switched off with `name = false` under `[transforms]`.
+    pub disabled_transforms: HashSet<String>,
+    /// User regex replacements from `[[custom_transform]]`, applied after the built-in ones.
+    pub custom_transforms: Vec<CustomTransform>,
+    /// Layout of `=>` chains clang-format wrapped, from `[chain]`.
+    pub chain: ChainStyle,
+    /// Language clang-format is told the source is in (`language`).
+    pub language: Language,
+    /// Name (without the language's extension) of the file clang-format is told it is
+    /// formatting, in place of the source's own name (`assume_filename`).
+    pub assume_filename: Option<String>,
+    /// Spaces around each operator family, from `[spacing]`.
+    pub spacing: Spacing,
+    /// Extensions of the files picked up in directories (`extensions`).
+    pub extensions: Extensions,
+}
+
+/// The language clang-format formats ChucK as, chosen through the extension of the
+/// `--assume-filename` passed to it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    Java,
+    Cpp,
+}
+
+impl Language {
+    /// Parses a language name as written in `--assume-language` and `.chuckfmt.toml`.
+    pub fn parse(name: &str) -> Option<Language> {
+        match name {
+            "java" => Some(Language::Java),
+            "cpp" => Some(Language::Cpp),
+            _ => None,
+        }
+    }
+
+    /// The file extension that selects this language in clang-format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Language::Java => "java",
+            Language::Cpp => "cpp",
+        }
+    }
+}
+
+/// The file extensions recognized as ChucK source, without their leading dot: those looked for
+/// when walking directories and required by `--only-ck`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extensions(Vec<String>);
+
+impl Extensions {
+    /// Recognized when neither `--extension` nor `extensions` says otherwise.
+    pub const DEFAULT: &[&str] = &["ck", "chuck"];
+
+    /// The given extensions, with or without a leading dot; `None` if there are none or one
+    /// is empty.
+    pub fn new<S: AsRef<str>>(extensions: &[S]) -> Option<Extensions> {
+        let extensions: Vec<String> = extensions
+            .iter()
+            .map(|e| e.as_ref().trim_start_matches('.').to_string())
+            .collect();
+        if extensions.is_empty() || extensions.iter().any(String::is_empty) {
+            return None;
+        }
+        Some(Extensions(extensions))
+    }
+
+    /// Whether `path` ends in one of the extensions.
+    pub fn matches(&self, path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|e| self.0.iter().any(|ext| e == ext.as_str()))
+    }
+
+    /// The extensions, without their leading dot.
+    pub fn as_slice(&self) -> &[String] {
+        &self.0
+    }
+}
+
+impl Default for Extensions {
+    fn default() -> Self {
+        Extensions(Extensions::DEFAULT.iter().map(|e| e.to_string()).collect())
+    }
+}
+
+/// How [`crate::transforms::reflow_chains`] lays out a chain broken across lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainStyle {
+    /// Whether wrapped chains are reflowed at all (`reflow`). Off by default, so clang-format's
+    /// own `ContinuationIndentWidth` decides the layout unless a project asks for this one.
+    pub reflow: bool,
+    /// Spaces continuation lines are indented past the first line of the chain (`indent`).
+    pub indent: usize,
+    /// Put the `=>` at the start of continuation lines instead of the end of the line before
+    /// (`break = "before"` rather than `"after"`).
+    pub break_before: bool,
+}
+
+impl Default for ChainStyle {
+    fn default() -> Self {
+        ChainStyle {
+            reflow: false,
+            indent: 4,
+            break_before: false,
+        }
+    }
+}
+
+/// Whether an operator family gets a single space on each side (`"spaced"`, the default) or
+/// none (`"tight"`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OperatorSpacing {
+    #[default]
+    Spaced,
+    Tight,
+}
+
+impl OperatorSpacing {
+    /// Parses a spacing name as written in `[spacing]` and `--spacing`.
+    pub fn parse(name: &str) -> Option<OperatorSpacing> {
+        match name {
+            "spaced" => Some(OperatorSpacing::Spaced),
+            "tight" => Some(OperatorSpacing::Tight),
+            _ => None,
+        }
+    }
+}
+
+/// Spacing of each operator family, applied by [`crate::transforms::apply_spacing`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Spacing {
+    /// `=>`, `@=>`, `=<` and the compound chucks (`+=>`, ...): `a => b` or `a=>b`.
+    pub chuck: OperatorSpacing,
+    /// `=^`: `a =^ b` or `a=^b`.
+    pub upchuck: OperatorSpacing,
+    /// `<<<` and `>>>`: `<<< x >>>` or `<<<x>>>`.
+    pub print: OperatorSpacing,
+    /// The `~` of `spork ~`: `spork ~ foo()` or `spork ~foo()`.
+    pub spork: OperatorSpacing,
+}
+
+impl Spacing {
+    /// Operator families as named in `[spacing]` and `--spacing`.
+    pub const FAMILIES: &[&str] = &["chuck", "upchuck", "print", "spork"];
+
+    /// Sets the spacing of the family called `family`; false if there is no such family.
+    pub fn set(&mut self, family: &str, spacing: OperatorSpacing) -> bool {
+        let field = match family {
+            "chuck" => &mut self.chuck,
+            "upchuck" => &mut self.upchuck,
+            "print" => &mut self.print,
+            "spork" => &mut self.spork,
+            _ => return false,
+        };
+        *field = spacing;
+        true
+    }
+}
+
+/// A `[[custom_transform]]` entry: every match of `pattern` is replaced by `replacement`
+/// (which may refer to capture groups as `$1`, `${name}`).
+#[derive(Debug, Clone)]
+pub struct CustomTransform {
+    pub pattern: Regex,
+    pub replacement: String,
+}
+
+impl Config {
+    /// Loads the nearest `.chuckfmt.toml` found in `dir` or any of its ancestors, or the default
+    /// config if there is none.
+    pub fn discover(dir: &Path) -> Result<Config, FmtError> {
+        match Config::find(dir) {
+            Some(path) => Config::load(&path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    /// The nearest `.chuckfmt.toml` in `dir` or any of its ancestors.
+    pub fn find(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .map(|d| d.join(CONFIG_FILE_NAME))
+            .find(|p| p.is_file())
+    }
+
+    /// Loads a config file.
+    pub fn load(path: &Path) -> Result<Config, FmtError> {
+        let text = fs::read_to_string(path).map_err(|source| FmtError::ReadFile {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Config::parse(&text).map_err(|message| FmtError::Config {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+
+    /// Parses the contents of a config file.
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        // (line of first key, pattern, replacement) per [[custom_transform]] item
+        let mut custom: Vec<(usize, Option<String>, Option<String>)> = Vec::new();
+
+        for entry in parse_toml(text)? {
+            let at = |msg: String| format!("line {}: {msg}", entry.line);
+            match (entry.table.as_str(), entry.key.as_str()) {
+                ("custom_transform", key @ ("pattern" | "replacement")) => {
+                    let Value::Str(value) = entry.value else {
+                        return Err(at(format!("custom_transform {key} must be a string")));
+                    };
+                    let item = entry.item.unwrap_or(0);
+                    if custom.len() <= item {
+                        custom.resize(item + 1, (entry.line, None, None));
+                    }
+                    if key == "pattern" {
+                        custom[item].1 = Some(value);
+                    } else {
+                        custom[item].2 = Some(value);
+                    }
+                }
+                ("transforms", name) => {
+                    if !TRANSFORM_NAMES.contains(&name) {
+                        return Err(at(format!(
+                            "unknown transform '{name}' (expected one of: {})",
+                            TRANSFORM_NAMES.join(", ")
+                        )));
+                    }
+                    match entry.value {
+                        Value::Bool(true) => config.disabled_transforms.remove(name),
+                        Value::Bool(false) => config.disabled_transforms.insert(name.to_string()),
+                        _ => return Err(at(format!("transform '{name}' must be true or false"))),
+                    };
+                }
+                ("chain", "reflow") => match entry.value {
+                    Value::Bool(reflow) => config.chain.reflow = reflow,
+                    _ => return Err(at("chain reflow must be true or false".to_string())),
+                },
+                ("chain", "indent") => match entry.value {
+                    Value::Int(n) if (0..=16).contains(&n) => config.chain.indent = n as usize,
+                    _ => {
+                        return Err(at(
+                            "chain indent must be an integer from 0 to 16".to_string()
+                        ));
+                    }
+                },
+                ("chain", "break") => match entry.value {
+                    Value::Str(s) if s == "after" || s == "before" => {
+                        config.chain.break_before = s == "before";
+                    }
+                    _ => return Err(at(r#"chain break must be "after" or "before""#.to_string())),
+                },
+                ("spacing", family) => {
+                    let invalid = || at(format!(r#"spacing {family} must be "spaced" or "tight""#));
+                    let spacing = match &entry.value {
+                        Value::Str(s) => OperatorSpacing::parse(s).ok_or_else(invalid)?,
+                        _ => return Err(invalid()),
+                    };
+                    if !config.spacing.set(family, spacing) {
+                        return Err(at(format!(
+                            "unknown operator family '{family}' in [spacing] (expected one of: {})",
+                            Spacing::FAMILIES.join(", ")
+                        )));
+                    }
+                }
+                ("", "language") => {
+                    let invalid = || at(r#"language must be "java" or "cpp""#.to_string());
+                    config.language = match &entry.value {
+                        Value::Str(s) => Language::parse(s).ok_or_else(invalid)?,
+                        _ => return Err(invalid()),
+                    };
+                }
+                ("", "assume_filename") => {
+                    let invalid = || {
+                        at("assume_filename must be a file name without a directory".to_string())
+                    };
+                    let Value::Str(name) = &entry.value else {
+                        return Err(invalid());
+                    };
+                    // the language's extension is appended anyway
+                    let stem = name
+                        .strip_suffix(".java")
+                        .or_else(|| name.strip_suffix(".cpp"))
+                        .unwrap_or(name);
+                    if stem.is_empty() || stem.contains(['/', '\\']) {
+                        return Err(invalid());
+                    }
+                    config.assume_filename = Some(stem.to_string());
+                }
+                ("", "extensions") => {
+                    let invalid = || {
+                        at("extensions must be a non-empty array of non-empty strings".to_string())
+                    };
+                    let Value::Array(values) = &entry.value else {
+                        return Err(invalid());
+                    };
+                    let names = values
+                        .iter()
+                        .map(|v| match v {
+                            Value::Str(s) => Ok(s.as_str()),
+                            _ => Err(invalid()),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    config.extensions = Extensions::new(&names).ok_or_else(invalid)?;
+                }
+                ("", key) => return Err(at(format!("unknown option '{key}'"))),
+                (table, key) => return Err(at(format!("unknown option '{key}' in [{table}]"))),
+            }
+        }
+
+        for (line, pattern, replacement) in custom {
+            let (Some(pattern), Some(replacement)) = (pattern, replacement) else {
+                return Err(format!(
+                    "line {line}: custom_transform needs both `pattern` and `replacement`"
+                ));
+            };
+            let pattern = Regex::new(&pattern)
+                .map_err(|e| format!("line {line}: invalid custom_transform pattern: {e}"))?;
+            config.custom_transforms.push(CustomTransform {
+                pattern,
+                replacement,
+            });
+        }
+
+        Ok(config)
+    }
+
+    /// Whether the post-formatting transform called `name` should run.
+    pub fn transform_enabled(&self, name: &str) -> bool {
+        !self.disabled_transforms.contains(name)
+    }
+}
+
+// -------------------- TOML subset parser --------------------
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    Array(Vec<Value>),
+}
+
+/// One `key = value` pair together with the table it belongs to ("" for the root table) and,
+/// for `[[array-of-tables]]`, the index of the item within that array.
+#[derive(Debug)]
+struct Entry {
+    table: String,
+    item: Option<usize>,
+    key: String,
+    value: Value,
+    line: usize,
+}
+
+fn parse_toml(text: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    let mut table = String::new();
+    let mut item = None;
+    let mut array_lens: Vec<(String, usize)> = Vec::new();
+
+    for (idx, raw) in text.lines().enumerate() {
+        let line = idx + 1;
+        let at = |msg: &str| format!("line {line}: {msg}");
+        let content = strip_comment(raw).trim();
+        if content.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = content.strip_prefix("[[") {
+            let name = header
+                .strip_suffix("]]")
+                .ok_or_else(|| at("unterminated table header"))?
+                .trim();
+            if name.is_empty() {
+                return Err(at("empty table name"));
+            }
+            let index = match array_lens.iter_mut().find(|(n, _)| n == name) {
+                Some((_, len)) => {
+                    *len += 1;
+                    *len - 1
+                }
+                None => {
+                    array_lens.push((name.to_string(), 1));
+                    0
+                }
+            };
+            table = name.to_string();
+            item = Some(index);
+            continue;
+        }
+
+        if let Some(header) = content.strip_prefix('[') {
+            let name = header
+                .strip_suffix(']')
+                .ok_or_else(|| at("unterminated table header"))?
+                .trim();
+            if name.is_empty() {
+                return Err(at("empty table name"));
+            }
+            table = name.to_string();
+            item = None;
+            continue;
+        }
+
+        let (key, value) = content
+            .split_once('=')
+            .ok_or_else(|| at("expected `key = value`"))?;
+        let key = key.trim().trim_matches('"');
+        if key.is_empty() {
+            return Err(at("missing key"));
+        }
+        let (value, rest) = parse_value(value.trim()).map_err(|e| at(&e))?;
+        if !rest.trim().is_empty() {
+            return Err(at("unexpected text after value"));
+        }
+
+        entries.push(Entry {
+            table: table.clone(),
+            item,
+            key: key.to_string(),
+            value,
+            line,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Cuts a trailing `# comment`, ignoring `#` inside strings.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' => return &line[..i],
+            None => {}
+        }
+    }
+    line
+}
+
+/// Parses one value from the start of `s`, returning it and the unparsed remainder.
+fn parse_value(s: &str) -> Result<(Value, &str), String> {
+    if let Some(rest) = s.strip_prefix("true") {
+        return Ok((Value::Bool(true), rest));
+    }
+    if let Some(rest) = s.strip_prefix("false") {
+        return Ok((Value::Bool(false), rest));
+    }
+
+    if let Some(body) = s.strip_prefix('\'') {
+        // Literal string: no escapes
+        let end = body.find('\'').ok_or("unterminated string")?;
+        return Ok((Value::Str(body[..end].to_string()), &body[end + 1..]));
+    }
+
+    if let Some(body) = s.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = body.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::Str(out), &body[i + 1..])),
+                '\\' => match chars.next().map(|(_, e)| e) {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('r') => out.push('\r'),
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some(e) => return Err(format!("unsupported escape `\\{e}`")),
+                    None => break,
+                },
+                _ => out.push(c),
+            }
+        }
+        return Err("unterminated string".to_string());
+    }
+
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut items = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(items), after));
+            }
+            let (item, after) = parse_value(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return Err("expected `,` or `]` in array".to_string());
+            }
+        }
+    }
+
+    let end = s
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '+' || c == '_'))
+        .unwrap_or(s.len());
+    let (number, rest) = s.split_at(end);
+    number
+        .replace('_', "")
+        .parse::<i64>()
+        .map(|n| (Value::Int(n), rest))
+        .map_err(|_| format!("invalid value `{s}`"))
+}
@@ -0,0 +1,76 @@
+//! Checks of formatted output for spots clang-format probably misread.
+//!
+//! clang-format parses ChucK as Java, and a construct it can't make sense of is usually left
+//! oddly spaced rather than reported. [`check`] looks for the telltale results so they can be
+//! pointed out to the user; it never changes the text.
+
+use crate::{Segment, parse_segments};
+use lazy_regex::regex;
+
+/// A spot in formatted output that probably needs a look by hand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// 1-based line number in the checked text.
+    pub line: usize,
+    pub message: String,
+}
+
+/// The suspicious spots in `output`, in line order. String literals and comments are ignored.
+pub fn check(output: &str) -> Vec<Diagnostic> {
+    let code = code_only(output);
+    let at = |offset: usize, message: &str| Diagnostic {
+        line: code[..offset].matches('\n').count() + 1,
+        message: message.to_string(),
+    };
+    let mut found = Vec::new();
+
+    // a print is one `<<<` and one `>>>`, within a statement
+    let unclosed = "`<<<` is never closed by a `>>>`";
+    let mut open = None;
+    for m in regex!(r"<<<|>>>|;").find_iter(&code) {
+        match (m.as_str(), open) {
+            ("<<<", Some(start)) => {
+                found.push(at(start, unclosed));
+                open = Some(m.start());
+            }
+            ("<<<", None) => open = Some(m.start()),
+            (">>>", Some(_)) => open = None,
+            (">>>", None) => found.push(at(m.start(), "`>>>` without a `<<<` before it")),
+            (_, Some(start)) => {
+                found.push(at(start, unclosed));
+                open = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = open {
+        found.push(at(start, unclosed));
+    }
+
+    // a chain cut in two by a statement boundary
+    for m in regex!(r"(?:\A|[;{}])\s*(?:[-+*/%&|^@]|<<|>>)?=>").find_iter(&code) {
+        found.push(at(m.end() - 2, "`=>` with nothing to chuck from"));
+    }
+    for m in regex!(r"=>\s*[;,)}\]]").find_iter(&code) {
+        found.push(at(m.start(), "`=>` with nothing to chuck to"));
+    }
+
+    found.sort_by_key(|d| d.line);
+    found
+}
+
+/// `s` with every literal and comment replaced by a stand-in operand, keeping its line breaks
+/// so offsets still fall on the right line.
+fn code_only(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for segment in parse_segments(s) {
+        match segment {
+            Segment::Code(code) => out.push_str(&code),
+            Segment::Literal(text) | Segment::Comment(text) => {
+                out.push('0');
+                out.extend(text.chars().filter(|&c| c == '\n'));
+            }
+        }
+    }
+    out
+}
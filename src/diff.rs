@@ -0,0 +1,234 @@
+//! Minimal line-based unified diff (Myers' algorithm), used by `--diff`.
+
+use std::collections::{HashMap, HashSet};
+
+/// Lines of context shown around each change.
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Renders a unified diff between `old` and `new`, or an empty string if they are identical.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+
+    let a: Vec<&str> = old.split_inclusive('\n').collect();
+    let b: Vec<&str> = new.split_inclusive('\n').collect();
+
+    // (op, index into a, index into b)
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0usize, 0usize);
+    for op in diff_ops(&a, &b) {
+        lines.push((op, i, j));
+        match op {
+            Op::Equal => {
+                i += 1;
+                j += 1;
+            }
+            Op::Delete => i += 1,
+            Op::Insert => j += 1,
+        }
+    }
+
+    let mut out = format!("--- {old_name}\n+++ {new_name}\n");
+    let mut start = 0usize;
+    while let Some(first) = lines[start..]
+        .iter()
+        .position(|l| l.0 != Op::Equal)
+        .map(|p| p + start)
+    {
+        // Grow the hunk while the next change is close enough to share context
+        let mut last = first;
+        for (k, l) in lines.iter().enumerate().skip(first) {
+            if l.0 != Op::Equal {
+                last = k;
+            } else if k - last > 2 * CONTEXT {
+                break;
+            }
+        }
+
+        let hunk = &lines[first.saturating_sub(CONTEXT)..(last + 1 + CONTEXT).min(lines.len())];
+        let old_len = hunk.iter().filter(|l| l.0 != Op::Insert).count();
+        let new_len = hunk.iter().filter(|l| l.0 != Op::Delete).count();
+        // Unified diff line numbers are 1-based; an empty range names the line before it
+        let old_start = hunk[0].1 + usize::from(old_len > 0);
+        let new_start = hunk[0].2 + usize::from(new_len > 0);
+        out.push_str(&format!(
+            "@@ -{old_start},{old_len} +{new_start},{new_len} @@\n"
+        ));
+
+        for &(op, oi, ni) in hunk {
+            let (prefix, text) = match op {
+                Op::Equal => (' ', a[oi]),
+                Op::Delete => ('-', a[oi]),
+                Op::Insert => ('+', b[ni]),
+            };
+            out.push(prefix);
+            out.push_str(text);
+            if !text.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+
+        start = (last + 1 + CONTEXT).min(lines.len());
+    }
+
+    out
+}
+
+/// Computes a shortest edit script turning `a` into `b`.
+fn diff_ops(a: &[&str], b: &[&str]) -> Vec<Op> {
+    // Lines are compared as numbers, and a line with no equal on the other side can only be
+    // deleted or inserted, so the search runs without them (a rewrite of every line then
+    // costs nothing) and they are put back in place afterwards.
+    let mut ids = HashMap::new();
+    let a_ids = number_lines(a, &mut ids);
+    let b_ids = number_lines(b, &mut ids);
+    let in_a: HashSet<usize> = a_ids.iter().copied().collect();
+    let in_b: HashSet<usize> = b_ids.iter().copied().collect();
+    let kept_a: Vec<usize> = (0..a.len()).filter(|&i| in_b.contains(&a_ids[i])).collect();
+    let kept_b: Vec<usize> = (0..b.len()).filter(|&j| in_a.contains(&b_ids[j])).collect();
+    let ka: Vec<usize> = kept_a.iter().map(|&i| a_ids[i]).collect();
+    let kb: Vec<usize> = kept_b.iter().map(|&j| b_ids[j]).collect();
+    let mut kept_ops = Vec::with_capacity(ka.len() + kb.len());
+    diff_into(&ka, &kb, &mut kept_ops);
+
+    let mut ops = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    let (mut ki, mut kj) = (0, 0);
+    for op in kept_ops {
+        // the lines dropped before this one's, then the line itself
+        let (to_i, to_j) = match op {
+            Op::Equal => (kept_a[ki], kept_b[kj]),
+            Op::Delete => (kept_a[ki], j),
+            Op::Insert => (i, kept_b[kj]),
+        };
+        ops.extend(std::iter::repeat_n(Op::Delete, to_i - i));
+        ops.extend(std::iter::repeat_n(Op::Insert, to_j - j));
+        ops.push(op);
+        (i, j) = (to_i, to_j);
+        match op {
+            Op::Equal => {
+                (i, j) = (i + 1, j + 1);
+                (ki, kj) = (ki + 1, kj + 1);
+            }
+            Op::Delete => {
+                i += 1;
+                ki += 1;
+            }
+            Op::Insert => {
+                j += 1;
+                kj += 1;
+            }
+        }
+    }
+    ops.extend(std::iter::repeat_n(Op::Delete, a.len() - i));
+    ops.extend(std::iter::repeat_n(Op::Insert, b.len() - j));
+    ops
+}
+
+/// The number of each line of `lines`, the same for equal lines, handing out new ones from
+/// `ids`.
+fn number_lines<'a>(lines: &[&'a str], ids: &mut HashMap<&'a str, usize>) -> Vec<usize> {
+    lines
+        .iter()
+        .map(|line| {
+            let next = ids.len();
+            *ids.entry(*line).or_insert(next)
+        })
+        .collect()
+}
+
+/// Appends a shortest edit script turning `a` into `b` to `ops`, in linear space: the middle
+/// snake of an optimal path splits the problem in two, which are solved the same way.
+fn diff_into<T: PartialEq>(a: &[T], b: &[T], ops: &mut Vec<Op>) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+    ops.extend(std::iter::repeat_n(Op::Equal, prefix));
+
+    if a.is_empty() {
+        ops.extend(std::iter::repeat_n(Op::Insert, b.len()));
+    } else if b.is_empty() {
+        ops.extend(std::iter::repeat_n(Op::Delete, a.len()));
+    } else {
+        // with the common ends stripped and both sides left, at least two edits remain, so
+        // both halves are smaller than the whole
+        let (x, y, u, v) = middle_snake(a, b);
+        diff_into(&a[..x], &b[..y], ops);
+        ops.extend(std::iter::repeat_n(Op::Equal, u - x));
+        diff_into(&a[u..], &b[v..], ops);
+    }
+
+    ops.extend(std::iter::repeat_n(Op::Equal, suffix));
+}
+
+/// The middle snake of a shortest edit script turning `a` into `b`, as its start and end
+/// `(x, y, u, v)`: searching from both ends at once (the backward search on the reversed
+/// sequences) until the furthest-reaching paths overlap.
+fn middle_snake<T: PartialEq>(a: &[T], b: &[T]) -> (usize, usize, usize, usize) {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let delta = n - m;
+    let max = (n + m + 1) / 2;
+    let offset = max + 1;
+    // furthest x reached on each diagonal k = x - y, forward and backward
+    let mut forward = vec![0isize; 2 * offset as usize + 1];
+    let mut backward = vec![0isize; 2 * offset as usize + 1];
+    let at = |k: isize| (k + offset) as usize;
+    // the furthest x on diagonal k in round d, from the paths of round d - 1
+    let next = |v: &[isize], k: isize, d: isize| {
+        if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+            v[at(k + 1)]
+        } else {
+            v[at(k - 1)] + 1
+        }
+    };
+
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let (x0, y0) = (next(&forward, k, d), next(&forward, k, d) - k);
+            let (mut x, mut y) = (x0, y0);
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[at(k)] = x;
+            // an odd delta meets a backward path of the previous round
+            if delta % 2 != 0 && (delta - k).abs() < d && x + backward[at(delta - k)] >= n {
+                return (x0 as usize, y0 as usize, x as usize, y as usize);
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let (x0, y0) = (next(&backward, k, d), next(&backward, k, d) - k);
+            let (mut x, mut y) = (x0, y0);
+            while x < n && y < m && a[(n - 1 - x) as usize] == b[(m - 1 - y) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[at(k)] = x;
+            // an even delta meets a forward path of this round
+            if delta % 2 == 0 && (delta - k).abs() <= d && x + forward[at(delta - k)] >= n {
+                return (
+                    (n - x) as usize,
+                    (m - y) as usize,
+                    (n - x0) as usize,
+                    (m - y0) as usize,
+                );
+            }
+        }
+    }
+    unreachable!("the forward and backward searches meet by round {max}")
+}
@@ -0,0 +1,159 @@
+//! `.gitignore`/`.ignore` rules for directory walks.
+//!
+//! Understands the common subset of gitignore syntax: `#` comments, `!` negation, a trailing `/`
+//! for directories only, a leading or inner `/` anchoring the pattern to its file's directory,
+//! and the `*`, `?`, `[...]` and `**` wildcards. The last matching rule wins.
+
+use chuckfmt::FmtError;
+use lazy_regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Names of the ignore files read in every directory walked.
+const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore"];
+
+#[derive(Debug, Clone)]
+struct Rule {
+    /// Directory of the ignore file; the pattern matches paths relative to it.
+    base: PathBuf,
+    pattern: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// The rules in effect for one directory: those of its ignore files and of its ancestors'.
+#[derive(Debug, Clone, Default)]
+pub struct Ignore {
+    rules: Vec<Rule>,
+}
+
+impl Ignore {
+    /// The rules for walking `root`: those from its ancestors' ignore files up to the top of
+    /// the git work tree it is in (if any), and its own.
+    pub fn for_root(root: &Path) -> Result<Ignore, FmtError> {
+        let root = std::path::absolute(root).unwrap_or_else(|_| root.to_path_buf());
+        let mut dirs: Vec<&Path> = Vec::new();
+        for dir in root.ancestors() {
+            dirs.push(dir);
+            if dir.join(".git").exists() {
+                break;
+            }
+        }
+        // without a work tree above, only the root's own ignore files apply
+        if !dirs.last().is_some_and(|d| d.join(".git").exists()) {
+            dirs.truncate(1);
+        }
+
+        let mut ignore = Ignore::default();
+        for dir in dirs.into_iter().rev() {
+            ignore = ignore.enter(dir)?;
+        }
+        Ok(ignore)
+    }
+
+    /// The rules for `dir`, a subdirectory of the one these rules are for.
+    pub fn enter(&self, dir: &Path) -> Result<Ignore, FmtError> {
+        let mut ignore = self.clone();
+        for name in IGNORE_FILES {
+            let path = dir.join(name);
+            let Ok(text) = fs::read_to_string(&path) else {
+                continue;
+            };
+            for (idx, line) in text.lines().enumerate() {
+                if let Some(rule) = parse_rule(dir, line).map_err(|message| FmtError::Config {
+                    path: path.clone(),
+                    message: format!("line {}: {message}", idx + 1),
+                })? {
+                    ignore.rules.push(rule);
+                }
+            }
+        }
+        Ok(ignore)
+    }
+
+    /// Whether `path` (a file, or a directory if `is_dir`) is ignored.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let Ok(relative) = path.strip_prefix(&rule.base) else {
+                continue;
+            };
+            let relative = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if rule.pattern.is_match(&relative) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+/// Parses one line of an ignore file in `base`, or `None` for blank lines and comments.
+fn parse_rule(base: &Path, line: &str) -> Result<Option<Rule>, String> {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let (negated, pattern) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line.strip_prefix('\\').unwrap_or(line)),
+    };
+    let (dir_only, pattern) = match pattern.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+    // a slash anywhere but at the end anchors the pattern to `base`
+    let anchored = pattern.contains('/');
+    let pattern = pattern.trim_start_matches('/');
+    if pattern.is_empty() {
+        return Ok(None);
+    }
+
+    let mut regex = String::from(if anchored { "^" } else { "^(?:.*/)?" });
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let class: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                let class = class
+                    .strip_prefix('!')
+                    .map_or(class.clone(), |c| format!("^{c}"));
+                regex.push_str(&format!("[{}]", class.replace('\\', "\\\\")));
+            }
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    regex.push_str(&lazy_regex::regex::escape(&escaped.to_string()));
+                }
+            }
+            c => regex.push_str(&lazy_regex::regex::escape(&c.to_string())),
+        }
+    }
+    // a directory match covers everything below it too
+    regex.push_str("(?:/.*)?$");
+
+    let pattern = Regex::new(&regex).map_err(|e| format!("invalid pattern `{line}`: {e}"))?;
+    Ok(Some(Rule {
+        base: base.to_path_buf(),
+        pattern,
+        negated,
+        dir_only,
+    }))
+}
@@ -0,0 +1,1438 @@
+//! Formatter for ChucK source code, built on top of `clang-format`.
+//!
+//! ChucK is fed to clang-format as Java, then ChucK-specific operators that clang-format splits
+//! apart (`=>`, `@=>`, `<<<`, `-->`, ...) are put back together by regex transforms.
+
+use lazy_regex::{Regex, regex, regex_captures, regex_replace_all};
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod config;
+pub mod diagnostics;
+pub mod markdown;
+pub mod transforms;
+
+pub use config::{
+    CONFIG_FILE_NAME, ChainStyle, Config, CustomTransform, Extensions, Language, OperatorSpacing,
+    Spacing,
+};
+
+/// Filename handed to clang-format's `--assume-filename` so ChucK is formatted as Java.
+pub const DEFAULT_ASSUME_FILENAME: &str = "code.java";
+
+/// Built-in clang-format style selected with `--style=chuck`, for users without a
+/// `.clang-format` of their own. Operands are never aligned, so long `=>` chains stay readable.
+pub const CHUCK_STYLE: &str = "{BasedOnStyle: Google, IndentWidth: 4, ContinuationIndentWidth: 4, \
+ColumnLimit: 100, BreakBeforeBraces: Attach, AlignOperands: DontAlign, \
+AlignConsecutiveAssignments: false, AlignConsecutiveDeclarations: false, \
+AlignTrailingComments: false, BreakBeforeBinaryOperators: None, \
+AllowShortFunctionsOnASingleLine: Empty, AllowShortIfStatementsOnASingleLine: Never, \
+AllowShortLoopsOnASingleLine: false, ReflowComments: false}";
+
+/// Wrapped around braced (`@import { "a.ck", "b.ck" }`) and multi-line `@import` statements so
+/// clang-format leaves them exactly as written; removed again by [`apply_transforms`].
+const IMPORT_BEGIN: &str = "// clang-format off\n//@chuckfmt-import\n";
+const IMPORT_END: &str = "\n//@chuckfmt-import-end\n// clang-format on";
+
+/// Prepares ChucK source for clang-format (e.g. protects `@import` statements).
+pub fn apply_pre_formatting_transforms(s: &str) -> String {
+    // clang-format's own off/on comments keep it out of `// chuckfmt: off` regions
+    let s = regex_replace_all!(
+        r"(?m)^([ \t]*)// chuckfmt: off[ \t]*$",
+        s,
+        |marker: &str, indent: &str| format!("{marker}\n{indent}// clang-format off")
+    );
+    let s = regex_replace_all!(
+        r"(?m)^([ \t]*)// chuckfmt: on[ \t]*$",
+        &s,
+        |marker: &str, indent: &str| format!("{indent}// clang-format on\n{marker}")
+    );
+    outside_off_regions(&s, |code| (pre_format_code(code), Vec::new())).0
+}
+
+/// The pre-formatting rewrites of [`apply_pre_formatting_transforms`] outside `// chuckfmt: off`
+/// regions.
+fn pre_format_code(s: &str) -> String {
+    // clang-format can't make sense of a braced module list or an import broken across lines,
+    // so those are fenced off verbatim (up to the end of their last line)
+    let s = regex_replace_all!(
+        r#"(?m)^[ \t]*@import(?:[ \t]*\{[^}]*\}|[ \t]*\n\s*"[^"\n]*")[^\n]*"#,
+        s,
+        |import: &str| format!("{IMPORT_BEGIN}{import}{IMPORT_END}")
+    );
+    // add a ";" after single-line @import statements to help clang-format parse them correctly,
+    // right after the path so a trailing comment stays after it
+    let s = regex_replace_all!(
+        r#"(?m)^([ \t]*@import[ \t]*"[^"\n]*")([ \t]*(?:(?://|/\*)[^\n]*)?)$"#,
+        &s,
+        "$1;$2"
+    );
+
+    // ChucK lexes `-->` as one operator, but Java sees `--` `>` and clang-format prints
+    // `a-- > b`, indistinguishable from a real decrement-then-compare. Hide it behind a Java
+    // binary operator ChucK doesn't have; `apply_transforms` turns it back into `-->`. To ChucK
+    // `instanceof` is a plain name, so a name made of it and underscores gets one more
+    // underscore to tell it apart, taken off again along with the swap.
+    let (masked, preserved) = mask_literals_and_comments(&s);
+    let masked = regex_replace_all!(r"\binstanceof_*\b", &masked, "${0}_");
+    let masked = regex_replace_all!(r"-->", &masked, " instanceof ");
+    unmask(&masked, &preserved)
+}
+
+/// Runs `f` over the text outside `// chuckfmt: off` / `// chuckfmt: on` regions, which are
+/// kept exactly as they are (markers included), returning the result and the names `f` reports
+/// for all pieces, in order and without repeats. A region left open runs to the end.
+fn outside_off_regions(
+    s: &str,
+    mut f: impl FnMut(&str) -> (String, Vec<String>),
+) -> (String, Vec<String>) {
+    let mut out = String::with_capacity(s.len());
+    let mut names: Vec<String> = Vec::new();
+    let mut run = |piece: &str, out: &mut String| {
+        let (text, fired) = f(piece);
+        out.push_str(&text);
+        for name in fired {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    };
+    let mut start = 0;
+    for region in
+        regex!(r"(?ms)^[ \t]*// chuckfmt: off[ \t]*$.*?(?:^[ \t]*// chuckfmt: on[ \t]*$|\z)")
+            .find_iter(s)
+    {
+        run(&s[start..region.start()], &mut out);
+        out.push_str(region.as_str());
+        start = region.end();
+    }
+    run(&s[start..], &mut out);
+    (out, names)
+}
+
+/// Names of the post-formatting transforms, as used in the `[transforms]` table of
+/// `.chuckfmt.toml` (the same order as [`transforms::ALL`]).
+pub const TRANSFORM_NAMES: &[&str] = &[
+    "chuck_arrow",
+    "unchuck",
+    "output",
+    "at_chuck",
+    "compound_chuck",
+    "upchuck",
+    "duration_colon",
+    "ternary",
+    "print_brackets",
+    "print_commas",
+    "polar",
+    "vector",
+    "complex",
+    "gruck",
+    "ungruck",
+    "leading_sign",
+    "sporks",
+    "multiplication",
+    "cast",
+    "reference",
+    "array_brackets",
+];
+
+// -------------------- Comment-preserving transform wrapper --------------------
+
+/// Segment types for parsing: code that should be transformed vs literals and comments that should
+/// be preserved.
+#[derive(Debug)]
+enum Segment {
+    Code(String),
+    Literal(String),
+    Comment(String),
+}
+
+/// Parses input into segments of code, string/char literals and comments.
+/// Handles // line comments, /* */ block comments, and escapes inside literals correctly.
+fn parse_segments(input: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut current_code = String::new();
+    let chars: Vec<char> = input.chars().collect();
+    let len = chars.len();
+    let mut i = 0;
+
+    while i < len {
+        // Check for string or char literal
+        if chars[i] == '"' || chars[i] == '\'' {
+            // Flush current code
+            if !current_code.is_empty() {
+                segments.push(Segment::Code(std::mem::take(&mut current_code)));
+            }
+            let quote = chars[i];
+            let mut literal = String::new();
+            literal.push(chars[i]);
+            i += 1;
+            while i < len {
+                if chars[i] == '\\' && i + 1 < len {
+                    literal.push(chars[i]);
+                    literal.push(chars[i + 1]);
+                    i += 2;
+                } else if chars[i] == quote {
+                    literal.push(chars[i]);
+                    i += 1;
+                    break;
+                } else {
+                    literal.push(chars[i]);
+                    i += 1;
+                }
+            }
+            segments.push(Segment::Literal(literal));
+            continue;
+        }
+
+        // Check for line comment
+        if i + 1 < len && chars[i] == '/' && chars[i + 1] == '/' {
+            // Flush current code
+            if !current_code.is_empty() {
+                segments.push(Segment::Code(std::mem::take(&mut current_code)));
+            }
+            let mut comment = String::new();
+            comment.push(chars[i]);
+            comment.push(chars[i + 1]);
+            i += 2;
+            // The newline stays in code so line-anchored transforms still see line breaks
+            while i < len && chars[i] != '\n' {
+                comment.push(chars[i]);
+                i += 1;
+            }
+            segments.push(Segment::Comment(comment));
+            continue;
+        }
+
+        // Check for block comment
+        if i + 1 < len && chars[i] == '/' && chars[i + 1] == '*' {
+            // Flush current code
+            if !current_code.is_empty() {
+                segments.push(Segment::Code(std::mem::take(&mut current_code)));
+            }
+            let mut comment = String::new();
+            comment.push(chars[i]);
+            comment.push(chars[i + 1]);
+            i += 2;
+            while i < len {
+                if i + 1 < len && chars[i] == '*' && chars[i + 1] == '/' {
+                    comment.push(chars[i]);
+                    comment.push(chars[i + 1]);
+                    i += 2;
+                    break;
+                } else {
+                    comment.push(chars[i]);
+                    i += 1;
+                }
+            }
+            segments.push(Segment::Comment(comment));
+            continue;
+        }
+
+        // Regular character
+        current_code.push(chars[i]);
+        i += 1;
+    }
+
+    // Flush remaining code
+    if !current_code.is_empty() {
+        segments.push(Segment::Code(current_code));
+    }
+
+    segments
+}
+
+/// Applies ChucK-specific formatting transforms to clang-format output.
+///
+/// Transforms only touch code: literals and comments are masked with placeholders rather than
+/// split out, so transforms still see the surrounding code as one piece (e.g. `<<< "a", b >>>;`
+/// or `a /* gain */ => b;`).
+pub fn apply_transforms(input: &str) -> String {
+    apply_transforms_with_config(input, &Config::default())
+}
+
+/// Like [`apply_transforms`], skipping the transforms `config` switches off.
+pub fn apply_transforms_with_config(input: &str, config: &Config) -> String {
+    apply_transforms_traced(input, config).0
+}
+
+/// Like [`apply_transforms_with_config`], also returning the names of the transforms that
+/// changed the text, in the order they ran.
+pub fn apply_transforms_traced(input: &str, config: &Config) -> (String, Vec<String>) {
+    // drop the fences apply_pre_formatting_transforms put around @import statements
+    let input = regex_replace_all!(
+        r"(?m)^[ \t]*// clang-format off\n[ \t]*//@chuckfmt-import\n",
+        input,
+        ""
+    );
+    let input = regex_replace_all!(
+        r"\n[ \t]*//@chuckfmt-import-end\n[ \t]*// clang-format on",
+        &input,
+        ""
+    );
+    // and the clang-format off/on comments it added next to `// chuckfmt: off`/`on`
+    let input = regex_replace_all!(
+        r"(?m)^([ \t]*// chuckfmt: off[ \t]*)\n[ \t]*// clang-format off\n",
+        &input,
+        "$1\n"
+    );
+    let input = regex_replace_all!(
+        r"(?m)\n[ \t]*// clang-format on\n([ \t]*// chuckfmt: on[ \t]*)$",
+        &input,
+        "\n$1"
+    );
+    outside_off_regions(&input, |code| {
+        let (masked, preserved) = mask_literals_and_comments(code);
+        let (output, fired) = transforms::apply_all(&masked, config);
+        (unmask(&output, &preserved), fired)
+    })
+}
+
+// -------------------- Literal masking --------------------
+
+// Private-use code points never appear in ChucK source nor match any transform pattern.
+// Placeholders stand in for both literals and comments.
+const MASK_OPEN: char = '\u{E000}';
+const MASK_CLOSE: char = '\u{E001}';
+
+fn mask_placeholder(index: usize) -> String {
+    format!("{MASK_OPEN}{index}{MASK_CLOSE}")
+}
+
+/// Replaces every literal and comment in `input` with a placeholder, returning the masked code
+/// and the original texts in placeholder order.
+fn mask_literals_and_comments(input: &str) -> (String, Vec<String>) {
+    let mut masked = String::new();
+    let mut preserved = Vec::new();
+
+    for segment in parse_segments(input) {
+        match segment {
+            Segment::Code(code) => masked.push_str(&code),
+            Segment::Literal(text) | Segment::Comment(text) => {
+                masked.push_str(&mask_placeholder(preserved.len()));
+                preserved.push(text);
+            }
+        }
+    }
+
+    (masked, preserved)
+}
+
+/// Replaces every placeholder produced by [`mask_placeholder`] with its original text.
+fn unmask(s: &str, literals: &[String]) -> String {
+    let s = regex_replace_all!(r"\x{E000}([0-9]+)\x{E001}", s, |_, index: &str| {
+        index
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| literals.get(i))
+            .map_or("", String::as_str)
+    });
+    s.into_owned()
+}
+
+// -------------------- Unicode operators --------------------
+
+/// Unicode glyphs that editors' snippets or pasted code put in place of ChucK operators, with the
+/// ASCII operator each stands for. `←` is left alone: it reads as neither `=<` nor `<=`.
+pub const UNICODE_OPERATORS: &[(char, &str)] = &[
+    ('⇒', "=>"),
+    ('⟹', "=>"),
+    ('→', "=>"),
+    ('⟶', "=>"),
+    ('≤', "<="),
+    ('≥', ">="),
+    ('≠', "!="),
+];
+
+/// Replaces the glyphs of [`UNICODE_OPERATORS`] with their ASCII operators, outside string
+/// literals and comments.
+pub fn normalize_unicode_operators(s: &str) -> String {
+    if !s.contains(|c| UNICODE_OPERATORS.iter().any(|&(glyph, _)| glyph == c)) {
+        return s.to_string();
+    }
+    let (masked, preserved) = mask_literals_and_comments(s);
+    let mut out = String::with_capacity(masked.len());
+    for c in masked.chars() {
+        match UNICODE_OPERATORS.iter().find(|&&(glyph, _)| glyph == c) {
+            Some((_, ascii)) => out.push_str(ascii),
+            None => out.push(c),
+        }
+    }
+    unmask(&out, &preserved)
+}
+
+// -------------------- Errors --------------------
+
+/// Errors returned by the formatter and the `chuckfmt` CLI.
+#[derive(Debug)]
+pub enum FmtError {
+    /// An explicitly given clang-format (`origin` is `CLANG_FORMAT_BIN` or
+    /// `--clang-format-path`) doesn't point at an executable file.
+    ClangFormatNotExecutable { path: PathBuf, origin: &'static str },
+    /// No clang-format binary was found on PATH.
+    ClangFormatNotFound,
+    /// clang-format is older than [`MIN_CLANG_FORMAT_VERSION`] and `--require-version` was given.
+    ClangFormatTooOld { found: u32, minimum: u32 },
+    /// clang-format didn't finish within the allowed time and was killed.
+    ClangFormatTimedOut(Duration),
+    /// clang-format ran but exited unsuccessfully.
+    ClangFormatFailed { code: Option<i32>, stderr: String },
+    /// clang-format's output doesn't have the same code tokens as its input: it dropped (`lost`)
+    /// or invented (`gained`) code instead of only changing the layout.
+    ClangFormatChangedCode {
+        lost: Vec<String>,
+        gained: Vec<String>,
+    },
+    /// clang-format succeeded but wrote warnings to stderr while formatting `name`, and
+    /// `--fail-on-warnings` was given.
+    ClangFormatWarned { name: String },
+    /// `--check-install` found a problem with the setup.
+    InstallCheckFailed(String),
+    /// Launching or talking to the clang-format process failed.
+    ClangFormatIo {
+        action: &'static str,
+        source: io::Error,
+    },
+    /// Reading a source file failed.
+    ReadFile { path: PathBuf, source: io::Error },
+    /// A source file isn't valid UTF-8; `offset` is the position of the first invalid byte.
+    InvalidUtf8 { path: PathBuf, offset: usize },
+    /// Writing a formatted file failed; `step` says which part of the atomic write (creating the
+    /// temporary file next to it, copying permissions, or renaming it over `path`) and names the
+    /// temporary file.
+    WriteFile {
+        path: PathBuf,
+        step: String,
+        source: io::Error,
+    },
+    /// A `.chuckfmt.toml` file is malformed.
+    Config { path: PathBuf, message: String },
+    /// Reading a `--files` list failed.
+    ReadFileList { path: PathBuf, source: io::Error },
+    /// Reading a source file named on line `line` of the `--files` list `list` failed.
+    ReadListedFile {
+        path: PathBuf,
+        list: PathBuf,
+        line: usize,
+        source: io::Error,
+    },
+    /// Reading source from stdin failed.
+    Stdin(io::Error),
+    /// Writing formatted output to stdout failed.
+    Stdout(io::Error),
+    /// Some files of a multi-file run could not be formatted (each error was already reported).
+    FilesFailed { failed: usize, total: usize },
+    /// `--check`/`--diff` found files whose formatting would change.
+    Unformatted(usize),
+    /// A git command run for `--staged` failed (`message` is its stderr, trimmed).
+    Git { command: String, message: String },
+    /// `--watch` couldn't watch the files for changes or listen for Ctrl-C.
+    Watch(String),
+    /// Invalid command-line usage.
+    Usage(String),
+}
+
+impl fmt::Display for FmtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FmtError::ClangFormatNotExecutable { path, origin } => {
+                write!(f, "{origin} is set but not executable: {}", path.display())
+            }
+            FmtError::ClangFormatNotFound => write!(
+                f,
+                "clang-format not found.\n\
+                 - Install clang-format and ensure it's on PATH, or\n\
+                 - Set CLANG_FORMAT_BIN to the full path of clang-format.\n\
+                 Example: CLANG_FORMAT_BIN=/usr/bin/clang-format {} ...",
+                env!("CARGO_PKG_NAME")
+            ),
+            FmtError::ClangFormatTooOld { found, minimum } => write!(
+                f,
+                "clang-format {found} is older than the minimum supported version {minimum}"
+            ),
+            FmtError::ClangFormatTimedOut(limit) => write!(
+                f,
+                "clang-format did not finish within {} seconds and was killed",
+                limit.as_secs_f64()
+            ),
+            FmtError::ClangFormatFailed { code, stderr } => {
+                write!(f, "clang-format failed with exit code {code:?}")?;
+                if !stderr.trim().is_empty() {
+                    write!(f, ": {}", stderr.trim_end())?;
+                }
+                Ok(())
+            }
+            FmtError::InstallCheckFailed(problem) => write!(f, "install check failed: {problem}"),
+            FmtError::ClangFormatWarned { name } => {
+                write!(
+                    f,
+                    "{name}: clang-format printed warnings (--fail-on-warnings)"
+                )
+            }
+            FmtError::ClangFormatChangedCode { lost, gained } => {
+                let list = |tokens: &[String]| {
+                    let mut shown: Vec<String> =
+                        tokens.iter().take(5).map(|t| format!("`{t}`")).collect();
+                    if tokens.len() > 5 {
+                        shown.push(format!("and {} more", tokens.len() - 5));
+                    }
+                    shown.join(", ")
+                };
+                write!(f, "clang-format changed the code, not just its layout")?;
+                if !lost.is_empty() {
+                    write!(f, "; lost {}", list(lost))?;
+                }
+                if !gained.is_empty() {
+                    write!(f, "; gained {}", list(gained))?;
+                }
+                Ok(())
+            }
+            FmtError::ClangFormatIo { action, source } => write!(f, "{action}: {source}"),
+            FmtError::ReadFile { path, source } => {
+                write!(f, "failed to read {}: {source}", path.display())
+            }
+            FmtError::InvalidUtf8 { path, offset } => write!(
+                f,
+                "{} is not valid UTF-8 (invalid byte at offset {offset})",
+                path.display()
+            ),
+            FmtError::WriteFile { path, step, source } => {
+                write!(f, "failed to write {}: {step}: {source}", path.display())?;
+                // both the temporary file and the rename need write access to the directory
+                if source.kind() == io::ErrorKind::PermissionDenied {
+                    let dir = path.parent().filter(|d| !d.as_os_str().is_empty());
+                    write!(
+                        f,
+                        " (check that {} is writable)",
+                        dir.unwrap_or(Path::new(".")).display()
+                    )?;
+                }
+                Ok(())
+            }
+            FmtError::Config { path, message } => {
+                write!(f, "invalid config {}: {message}", path.display())
+            }
+            FmtError::ReadFileList { path, source } => {
+                write!(
+                    f,
+                    "failed to read --files list '{}': {source}",
+                    path.display()
+                )
+            }
+            FmtError::ReadListedFile {
+                path,
+                list,
+                line,
+                source,
+            } => write!(
+                f,
+                "failed to read {} (line {line} of --files list '{}'): {source}",
+                path.display(),
+                list.display()
+            ),
+            FmtError::Stdin(e) => write!(f, "failed to read stdin: {e}"),
+            FmtError::Stdout(e) => write!(f, "failed to write stdout: {e}"),
+            FmtError::FilesFailed { failed, total } => {
+                write!(f, "{failed} of {total} file(s) could not be formatted")
+            }
+            FmtError::Unformatted(n) => write!(f, "{n} file(s) need formatting"),
+            FmtError::Git { command, message } => write!(f, "{command} failed: {message}"),
+            FmtError::Watch(msg) => write!(f, "--watch failed: {msg}"),
+            FmtError::Usage(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl FmtError {
+    /// Exit status the `chuckfmt` CLI reports this error with: 2 for a usage error (bad or
+    /// conflicting arguments), 1 for anything that went wrong while formatting.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            FmtError::Usage(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl std::error::Error for FmtError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FmtError::ClangFormatIo { source, .. }
+            | FmtError::ReadFile { source, .. }
+            | FmtError::WriteFile { source, .. }
+            | FmtError::ReadFileList { source, .. }
+            | FmtError::ReadListedFile { source, .. }
+            | FmtError::Stdin(source)
+            | FmtError::Stdout(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// -------------------- Library entry point --------------------
+
+/// Formats ChucK source: pre-transforms, clang-format, then ChucK post-transforms.
+///
+/// `opts` are passed through to clang-format; `--assume-filename=code.java` is added unless
+/// already present (and `.java` appended to a ChucK one, see [`complete_assume_filename`]).
+/// clang-format is located the same way as the CLI does, once per process (see
+/// [`resolve_clang_format_cached`]).
+///
+/// This needs clang-format installed; without it the result is
+/// [`FmtError::ClangFormatNotFound`]. [`process_string`] with no binary runs the ChucK
+/// transforms alone, leaving the layout as written:
+///
+/// ```
+/// use chuckfmt::{Config, process_string};
+///
+/// let input = "spork~go();\n2 :: second => now;\n";
+/// let formatted = process_string(None, &[], None, &Config::default(), input).unwrap();
+/// assert_eq!(formatted.output, "spork ~ go();\n2::second => now;\n");
+/// ```
+///
+/// With clang-format:
+///
+/// ```
+/// match chuckfmt::format_chuck("SinOsc s=>dac;", &[]) {
+///     Ok(formatted) => assert_eq!(formatted.trim_end(), "SinOsc s => dac;"),
+///     Err(chuckfmt::FmtError::ClangFormatNotFound) => eprintln!("clang-format not found"),
+///     Err(e) => panic!("{e}"),
+/// }
+/// ```
+pub fn format_chuck(input: &str, opts: &[String]) -> Result<String, FmtError> {
+    let clang_format = resolve_clang_format_cached()?;
+    let mut opts = opts.to_vec();
+    expand_style_alias(&mut opts);
+    if !has_assume_filename(&opts) {
+        opts.push(format!("--assume-filename={DEFAULT_ASSUME_FILENAME}"));
+    }
+    complete_assume_filename(&mut opts, "java");
+    process_string(Some(&clang_format), &opts, None, &Config::default(), input).map(|f| f.output)
+}
+
+/// Replaces `--style=chuck` (any spelling, including `--style chuck`) with the embedded
+/// [`CHUCK_STYLE`].
+pub fn expand_style_alias(opts: &mut [String]) {
+    let is_style = |o: &str| o == "--style" || o == "-style";
+    for i in 0..opts.len() {
+        match opts[i].split_once('=') {
+            Some((name, "chuck")) if is_style(name) => {
+                opts[i] = format!("{name}={CHUCK_STYLE}");
+            }
+            None if i > 0 && opts[i] == "chuck" && is_style(&opts[i - 1]) => {
+                opts[i] = CHUCK_STYLE.to_string();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Sets clang-format's `ColumnLimit` to `limit` on top of whatever style `opts` select (the last
+/// `-style`/`--style`, in either form), adding a `--style` if there is none. Run it after
+/// [`expand_style_alias`] so it also applies to `--style=chuck`.
+///
+/// An inline `{...}` style gets its `ColumnLimit` replaced or added, a named style becomes
+/// `{BasedOnStyle: <name>, ColumnLimit: <limit>}`, and `file` (the default) inherits the
+/// `.clang-format` found for the source.
+pub fn apply_column_limit(opts: &mut Vec<String>, limit: u32) -> Result<(), FmtError> {
+    set_style_option(opts, "ColumnLimit", limit, "--column-limit")
+}
+
+/// Sets clang-format's `MaxEmptyLinesToKeep` to `keep` on top of whatever style `opts` select,
+/// the same way [`apply_column_limit`] sets `ColumnLimit`. Runs of more blank lines are
+/// shortened to `keep`; shorter ones are left as they are.
+pub fn apply_max_empty_lines(opts: &mut Vec<String>, keep: u32) -> Result<(), FmtError> {
+    set_style_option(opts, "MaxEmptyLinesToKeep", keep, "--keep-blank-lines")
+}
+
+/// Sets the style option `key` to `value` in the style `opts` select; `flag` names the chuckfmt
+/// option asking for it in errors.
+fn set_style_option(
+    opts: &mut Vec<String>,
+    key: &str,
+    value: u32,
+    flag: &str,
+) -> Result<(), FmtError> {
+    let is_style = |o: &str| o == "--style" || o == "-style";
+    // (index of the token holding the value, what precedes the value in it, the value)
+    let mut found = None;
+    for (i, opt) in opts.iter().enumerate() {
+        match opt.split_once('=') {
+            Some((name, value)) if is_style(name) => {
+                found = Some((i, format!("{name}="), value.to_string()));
+            }
+            None if is_style(opt) && i + 1 < opts.len() => {
+                found = Some((i + 1, String::new(), opts[i + 1].clone()));
+            }
+            _ => {}
+        }
+    }
+
+    let setting = format!("{key}: {value}");
+    let style = match found.as_ref().map(|(_, _, v)| v.trim()) {
+        Some(inline) if inline.starts_with('{') => {
+            let body = inline.trim_start_matches('{').trim_end_matches('}');
+            let existing = Regex::new(&format!(r"(^|,)\s*{key}\s*:\s*[^,]*")).unwrap();
+            let body = existing.replace_all(body, "");
+            let body = body.trim().trim_start_matches(',').trim();
+            if body.is_empty() {
+                format!("{{{setting}}}")
+            } else {
+                format!("{{{body}, {setting}}}")
+            }
+        }
+        None | Some("file") => format!("{{BasedOnStyle: InheritParentConfig, {setting}}}"),
+        Some(other) if other.starts_with("file:") => {
+            return Err(FmtError::Usage(format!(
+                "{flag} cannot be combined with -style=file:<path>"
+            )));
+        }
+        Some(name) => format!("{{BasedOnStyle: {name}, {setting}}}"),
+    };
+
+    match found {
+        Some((i, prefix, _)) => opts[i] = format!("{prefix}{style}"),
+        None => opts.push(format!("--style={style}")),
+    }
+    Ok(())
+}
+
+/// Whether `opts` already contains an `--assume-filename` option (any spelling).
+pub fn has_assume_filename(opts: &[String]) -> bool {
+    opts.iter().any(|o| {
+        o == "--assume-filename"
+            || o == "-assume-filename"
+            || o.starts_with("--assume-filename=")
+            || o.starts_with("-assume-filename=")
+    })
+}
+
+/// Appends `.{extension}` to every `--assume-filename` (any spelling) naming a ChucK file (one of
+/// [`Extensions::DEFAULT`]), which clang-format would otherwise format as C++ whatever the
+/// language chosen. Other names are the user's explicit choice and kept as given.
+pub fn complete_assume_filename(opts: &mut [String], extension: &str) {
+    let chuck = Extensions::default();
+    let is_chuck = |name: &str| chuck.matches(Path::new(name));
+    let mut value_next = false;
+    for opt in opts.iter_mut() {
+        let is_value = std::mem::take(&mut value_next);
+        if opt == "--assume-filename" || opt == "-assume-filename" {
+            value_next = true;
+            continue;
+        }
+        let name = match opt.split_once('=') {
+            Some(("--assume-filename" | "-assume-filename", name)) => name,
+            _ if is_value => opt.as_str(),
+            _ => continue,
+        };
+        if is_chuck(name) {
+            opt.push('.');
+            opt.push_str(extension);
+        }
+    }
+}
+
+// -------------------- clang-format resolution --------------------
+
+/// Locates the clang-format binary to use.
+///
+/// Resolution order:
+/// 1. `explicit` path, e.g. from `--clang-format-path` (must be executable)
+/// 2. `CLANG_FORMAT_BIN` environment variable (must be executable)
+/// 3. `clang-format` in PATH, then versioned names (`clang-format-22` down to `clang-format-10`)
+///    as installed by many Linux distros; `CHUCKFMT_CLANG_FORMAT_NAMES` (comma or whitespace
+///    separated) replaces this candidate list
+pub fn resolve_clang_format(explicit: Option<&Path>) -> Result<PathBuf, FmtError> {
+    let explicit = explicit
+        .map(|p| (p.to_path_buf(), "--clang-format-path"))
+        .or_else(|| env::var_os("CLANG_FORMAT_BIN").map(|p| (p.into(), "CLANG_FORMAT_BIN")));
+    if let Some((path, origin)) = explicit {
+        if is_executable(&path) {
+            return Ok(path);
+        }
+        return Err(FmtError::ClangFormatNotExecutable { path, origin });
+    }
+
+    for name in clang_format_candidates() {
+        if let Some(pb) = find_in_path(exe_name(&name)) {
+            return Ok(pb);
+        }
+    }
+
+    Err(FmtError::ClangFormatNotFound)
+}
+
+/// [`resolve_clang_format`] without an explicit path, probed only on the first successful call
+/// in a process: later calls return the same binary, even if `CLANG_FORMAT_BIN` or PATH changed
+/// in between.
+pub fn resolve_clang_format_cached() -> Result<PathBuf, FmtError> {
+    static RESOLVED: OnceLock<PathBuf> = OnceLock::new();
+
+    if let Some(path) = RESOLVED.get() {
+        return Ok(path.clone());
+    }
+    // failures aren't cached, so installing clang-format later is noticed
+    let path = resolve_clang_format(None)?;
+    Ok(RESOLVED.get_or_init(|| path).clone())
+}
+
+/// Oldest clang-format major version whose Java layout is known to round-trip ChucK well.
+pub const MIN_CLANG_FORMAT_VERSION: u32 = 14;
+
+/// Runs `clang-format --version`, returning its trimmed output
+/// (e.g. `Ubuntu clang-format version 18.1.3`).
+pub fn clang_format_version(clang: &Path) -> Result<String, FmtError> {
+    let output = Command::new(clang)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|source| FmtError::ClangFormatIo {
+            action: "failed to launch clang-format",
+            source,
+        })?;
+    if !output.status.success() {
+        return Err(FmtError::ClangFormatFailed {
+            code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Extracts the major version from `clang-format --version` output.
+pub fn parse_major_version(version: &str) -> Option<u32> {
+    let (_, major) = regex_captures!(r"version\s+([0-9]+)", version)?;
+    major.parse().ok()
+}
+
+/// Newest and oldest versioned clang-format names probed after the bare name.
+const NEWEST_CLANG_FORMAT: u32 = 22;
+const OLDEST_CLANG_FORMAT: u32 = 10;
+
+/// Binary names to look up on PATH, in priority order.
+fn clang_format_candidates() -> Vec<String> {
+    if let Ok(names) = env::var("CHUCKFMT_CLANG_FORMAT_NAMES") {
+        return names
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|n| !n.is_empty())
+            .map(str::to_string)
+            .collect();
+    }
+    std::iter::once("clang-format".to_string())
+        .chain(
+            (OLDEST_CLANG_FORMAT..=NEWEST_CLANG_FORMAT)
+                .rev()
+                .map(|v| format!("clang-format-{v}")),
+        )
+        .collect()
+}
+
+fn exe_name(base: &str) -> String {
+    if cfg!(windows) {
+        format!("{base}.exe")
+    } else {
+        base.to_string()
+    }
+}
+
+fn is_executable(p: &Path) -> bool {
+    if !p.is_file() {
+        return false;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(p)
+            .map(|m| (m.permissions().mode() & 0o111) != 0)
+            .unwrap_or(false)
+    }
+    #[cfg(windows)]
+    {
+        true
+    }
+}
+
+fn find_in_path(program: String) -> Option<PathBuf> {
+    if program.contains(std::path::MAIN_SEPARATOR) {
+        let p = PathBuf::from(program);
+        return if is_executable(&p) { Some(p) } else { None };
+    }
+
+    let path_var: OsString = env::var_os("PATH")?;
+    for dir in env::split_paths(&path_var) {
+        let candidate = dir.join(&program);
+        if is_executable(&candidate) {
+            return Some(candidate);
+        }
+        if cfg!(windows) && !program.to_lowercase().ends_with(".exe") {
+            let candidate_exe = dir.join(format!("{program}.exe"));
+            if is_executable(&candidate_exe) {
+                return Some(candidate_exe);
+            }
+        }
+    }
+    None
+}
+
+// -------------------- Safety check --------------------
+
+/// The code tokens of `s`: literals whole, runs of letters, digits and `_`, and every other
+/// character on its own (so `= >` and `=>` count the same). Comments are left out, since
+/// clang-format may reflow them.
+fn code_tokens(s: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for segment in parse_segments(s) {
+        match segment {
+            Segment::Code(code) => {
+                let mut word = String::new();
+                for c in code.chars() {
+                    if c.is_alphanumeric() || c == '_' {
+                        word.push(c);
+                        continue;
+                    }
+                    if !word.is_empty() {
+                        tokens.push(std::mem::take(&mut word));
+                    }
+                    if !c.is_whitespace() {
+                        tokens.push(c.to_string());
+                    }
+                }
+                if !word.is_empty() {
+                    tokens.push(word);
+                }
+            }
+            Segment::Literal(text) => tokens.push(text),
+            Segment::Comment(_) => {}
+        }
+    }
+    tokens
+}
+
+/// Whether `s` has code for clang-format to lay out: anything but comments and `@import` lines.
+fn has_code(s: &str) -> bool {
+    let without_imports = regex_replace_all!(r"(?m)^[ \t]*@import\b.*$", s, "");
+    !code_tokens(&without_imports).is_empty()
+}
+
+/// Fails unless `output` has the same code tokens as `input`, in any order.
+fn check_tokens_kept(input: &str, output: &str) -> Result<(), FmtError> {
+    let mut counts: BTreeMap<String, isize> = BTreeMap::new();
+    for token in code_tokens(input) {
+        *counts.entry(token).or_default() += 1;
+    }
+    for token in code_tokens(output) {
+        *counts.entry(token).or_default() -= 1;
+    }
+
+    let (mut lost, mut gained) = (Vec::new(), Vec::new());
+    for (token, count) in counts {
+        let side = if count > 0 { &mut lost } else { &mut gained };
+        side.extend(std::iter::repeat_n(token, count.unsigned_abs()));
+    }
+    if lost.is_empty() && gained.is_empty() {
+        Ok(())
+    } else {
+        Err(FmtError::ClangFormatChangedCode { lost, gained })
+    }
+}
+
+// -------------------- Cursor mapping --------------------
+
+/// The `-cursor` offset in clang-format options (`-cursor=N`, `--cursor=N` or `-cursor N`),
+/// with the position and number of the option tokens holding it.
+fn find_cursor(opts: &[String]) -> Option<(usize, usize, usize)> {
+    opts.iter().enumerate().find_map(|(i, opt)| {
+        let name = opt.split('=').next()?;
+        if name != "-cursor" && name != "--cursor" {
+            return None;
+        }
+        match opt.split_once('=') {
+            Some((_, value)) => Some((i, 1, value.parse().ok()?)),
+            None => Some((i, 2, opts.get(i + 1)?.parse().ok()?)),
+        }
+    })
+}
+
+/// Splits the `{ "Cursor": N, ... }` line clang-format prints before the code when given
+/// `-cursor` into the cursor and the rest of that line.
+fn split_cursor_header(output: &str) -> Option<(usize, String, &str)> {
+    let (header, cursor, rest) = regex_captures!(r#"^\{ "Cursor": (\d+), ([^\n]*)\n"#, output)?;
+    Some((
+        cursor.parse().ok()?,
+        rest.to_string(),
+        &output[header.len()..],
+    ))
+}
+
+/// Byte offsets of the characters formatting keeps: everything but whitespace, the BOM, the
+/// import fences, clang-format off/on comments and `;`s [`apply_pre_formatting_transforms`]
+/// adds, and the letters of the `instanceof` standing in for `-->` past its first three. A name
+/// made of `instanceof` and underscores keeps its first three letters too, so it lines up with
+/// the underscore added to it before formatting.
+fn significant_offsets(s: &str) -> Vec<usize> {
+    let mut skip = vec![false; s.len()];
+    let mut skip_range = |start: usize, end: usize| skip[start..end].fill(true);
+    for m in regex!(
+        r"(?m)^[ \t]*// clang-format off\n[ \t]*//@chuckfmt-import\n|\n[ \t]*//@chuckfmt-import-end\n[ \t]*// clang-format on"
+    )
+    .find_iter(s)
+    {
+        skip_range(m.start(), m.end());
+    }
+    for caps in regex!(
+        r"(?m)^[ \t]*// chuckfmt: off[ \t]*(\n[ \t]*// clang-format off)$|(\n[ \t]*// clang-format on)\n[ \t]*// chuckfmt: on[ \t]*$"
+    )
+    .captures_iter(s)
+    {
+        let added = caps.get(1).or(caps.get(2)).unwrap();
+        skip_range(added.start(), added.end());
+    }
+    for caps in regex!(r#"(?m)^[ \t]*@import[ \t]*"[^"\n]*"(;)"#).captures_iter(s) {
+        let semicolon = caps.get(1).unwrap();
+        skip_range(semicolon.start(), semicolon.end());
+    }
+    for m in regex!(r"\binstanceof_*\b").find_iter(s) {
+        skip_range(m.start() + 3, m.end());
+    }
+
+    s.char_indices()
+        .filter(|&(i, c)| !skip[i] && !c.is_whitespace() && c != '\u{FEFF}')
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Maps a byte `offset` in `before` to the matching spot in `after`, assuming the two differ
+/// only in whitespace and the pre-formatting rewrites: the cursor stays next to the same
+/// character of code.
+fn map_offset(before: &str, after: &str, offset: usize) -> usize {
+    let old = significant_offsets(before);
+    let new = significant_offsets(after);
+    // characters of code before the cursor
+    let k = old.partition_point(|&i| i < offset);
+    if old.get(k) == Some(&offset) {
+        // right in front of a character: stay in front of it
+        new.get(k).copied().unwrap_or(after.len())
+    } else if k == 0 {
+        0
+    } else {
+        // after a character (maybe past some whitespace): stay right after it
+        new.get(k - 1).map_or(after.len(), |&i| {
+            i + after[i..].chars().next().map_or(0, char::len_utf8)
+        })
+    }
+}
+
+// -------------------- Range formatting --------------------
+
+/// Removes the `-lines=N:M`, `-offset=N` and `-length=N` options (also `--name` and the
+/// two-token forms) from `opts`, returning the byte ranges of `input` they select, or `None` if
+/// there were none. Offsets count bytes of `original`, before the BOM and CRs were dropped.
+fn take_ranges(
+    opts: &mut Vec<String>,
+    original: &str,
+    input: &str,
+) -> Result<Option<Vec<(usize, usize)>>, FmtError> {
+    let mut lines = Vec::new();
+    let mut offsets = Vec::new();
+    let mut lengths = Vec::new();
+    let mut i = 0;
+    while i < opts.len() {
+        let (flag, inline) = match opts[i].split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (opts[i].clone(), None),
+        };
+        let name = flag.trim_start_matches('-');
+        if !flag.starts_with('-') || !matches!(name, "lines" | "offset" | "length") {
+            i += 1;
+            continue;
+        }
+        opts.remove(i);
+        let value = match inline {
+            Some(value) => value,
+            None if i < opts.len() => opts.remove(i),
+            None => return Err(FmtError::Usage(format!("{flag} needs a value"))),
+        };
+        let invalid = || FmtError::Usage(format!("invalid {flag} value: {value}"));
+        match name {
+            "lines" => {
+                let (first, last) = value.split_once(':').ok_or_else(invalid)?;
+                let first: usize = first.parse().map_err(|_| invalid())?;
+                let last: usize = last.parse().map_err(|_| invalid())?;
+                if first == 0 || last < first {
+                    return Err(invalid());
+                }
+                lines.push((first, last));
+            }
+            "offset" => offsets.push(value.parse::<usize>().map_err(|_| invalid())?),
+            _ => lengths.push(value.parse::<usize>().map_err(|_| invalid())?),
+        }
+    }
+    if lines.is_empty() && offsets.is_empty() && lengths.is_empty() {
+        return Ok(None);
+    }
+
+    // line N starts at line_starts[N - 1]
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let mut ranges: Vec<(usize, usize)> = lines
+        .into_iter()
+        .map(|(first, last)| {
+            let start = line_starts.get(first - 1).copied().unwrap_or(input.len());
+            let end = line_starts.get(last).map_or(input.len(), |&next| next - 1);
+            (start, end.max(start))
+        })
+        .collect();
+    // like clang-format, a lone -length counts from the start
+    if offsets.is_empty() && !lengths.is_empty() {
+        offsets.push(0);
+    }
+    for (k, &offset) in offsets.iter().enumerate() {
+        let start = map_offset(original, input, offset);
+        let end = lengths.get(k).map_or(input.len(), |&length| {
+            map_offset(original, input, offset + length)
+        });
+        ranges.push((start, end.max(start)));
+    }
+    Ok(Some(ranges))
+}
+
+/// Turns byte ranges of `input` into sorted, disjoint spans running from the end of the last
+/// character of code before each range to the end of its own last one, so the whitespace
+/// clang-format may rewrite in front of a range belongs to it and the rest stays untouched.
+fn code_spans(input: &str, ranges: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let code = significant_offsets(input);
+    let end_of_code_before = |at: usize| {
+        let k = code.partition_point(|&i| i < at);
+        k.checked_sub(1).map_or(0, |k| {
+            let i = code[k];
+            i + input[i..].chars().next().map_or(0, char::len_utf8)
+        })
+    };
+
+    let mut ranges = ranges.to_vec();
+    ranges.sort_unstable();
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in ranges {
+        let span = (end_of_code_before(start), end_of_code_before(end));
+        match spans.last_mut() {
+            Some(last) if span.0 <= last.1 => last.1 = last.1.max(span.1),
+            _ => spans.push((span.0, span.1.max(span.0))),
+        }
+    }
+    spans
+}
+
+// -------------------- Line endings --------------------
+
+/// Whether most line breaks in `s` are CRLF (Windows) rather than LF.
+fn is_mostly_crlf(s: &str) -> bool {
+    let crlf = s.matches("\r\n").count();
+    crlf * 2 > s.matches('\n').count()
+}
+
+/// Makes `output` end with a newline exactly when `input` did, so files kept without a final
+/// newline round-trip stably.
+fn match_trailing_newline(input: &str, mut output: String) -> String {
+    if input.ends_with('\n') {
+        if !output.is_empty() && !output.ends_with('\n') {
+            output.push('\n');
+        }
+    } else {
+        output.truncate(output.trim_end_matches(['\r', '\n']).len());
+    }
+    output
+}
+
+// -------------------- Running clang-format (stdin -> stdout capture) --------------------
+
+/// UTF-8 byte order mark, as left at the start of files by some Windows editors.
+const BOM: &str = "\u{FEFF}";
+
+/// Whether the first non-blank line of `s` is a `// chuckfmt: skip` (or `// chuckfmt-ignore`)
+/// comment, marking a file (e.g. a generated one) chuckfmt must leave alone.
+fn has_skip_directive(s: &str) -> bool {
+    s.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .is_some_and(|line| regex!(r"^//\s*chuckfmt(?::\s*skip|-ignore)$").is_match(line))
+}
+
+/// Output of formatting one input with [`process_string`].
+#[derive(Debug, Clone)]
+pub struct Formatted {
+    /// The formatted ChucK source.
+    pub output: String,
+    /// Whatever clang-format wrote to stderr (usually warnings), even when it succeeded.
+    pub stderr: String,
+    /// Transforms that changed the text, in order; custom ones as `custom_transform[N]`
+    /// (1-based, in config order).
+    pub transforms: Vec<String>,
+    /// With a `-cursor` option, the byte offset in `output` (after its first line) where the
+    /// cursor ends up. Like clang-format, `output` then starts with a `{ "Cursor": N, ... }` line.
+    pub cursor: Option<usize>,
+}
+
+/// Formats `input` with an already-resolved clang-format binary and the exact `opts` given.
+///
+/// With no clang-format binary only the ChucK transforms run: operators are normalized but the
+/// layout (indentation, line breaks, braces) is left as written. Empty or whitespace-only input,
+/// and input whose first non-blank line is `// chuckfmt: skip` (or `// chuckfmt-ignore`), is
+/// returned unchanged without running clang-format, and input with nothing but comments and
+/// `@import` lines only gets the ChucK transforms. clang-format is killed if it
+/// runs longer than `timeout`.
+pub fn process_string(
+    clang_format: Option<&Path>,
+    opts: &[String],
+    timeout: Option<Duration>,
+    config: &Config,
+    input: &str,
+) -> Result<Formatted, FmtError> {
+    // A BOM would hide the first line from `^`-anchored transforms; it is put back at the end
+    let original = input;
+    let (bom, input) = match input.strip_prefix(BOM) {
+        Some(rest) => (BOM, rest),
+        None => ("", input),
+    };
+    // Nothing to format: blank input, or a file marked `// chuckfmt: skip`, is returned as is,
+    // without spawning clang-format
+    if input.trim().is_empty() || has_skip_directive(input) {
+        let cursor = find_cursor(opts).map(|(_, _, offset)| offset.min(original.len()));
+        let mut output = original.to_string();
+        if let Some(offset) = cursor {
+            output.insert_str(
+                0,
+                &format!("{{ \"Cursor\": {offset}, \"IncompleteFormat\": false }}\n"),
+            );
+        }
+        return Ok(Formatted {
+            output,
+            stderr: String::new(),
+            transforms: Vec::new(),
+            cursor,
+        });
+    }
+    // Work on LF internally; the dominant line ending is restored at the end
+    let crlf = is_mostly_crlf(input);
+    let unix_input = input.replace("\r\n", "\n");
+    let pre_formatted = apply_pre_formatting_transforms(&unix_input);
+    let mut clang_opts = opts.to_vec();
+    let ranges = take_ranges(&mut clang_opts, original, &unix_input)?;
+    // clang-format would only reflow the comments of a file without code as Java ones
+    let clang_format = clang_format.filter(|_| has_code(&unix_input));
+    let (formatted, stderr, cursor) = match clang_format {
+        Some(clang) => {
+            // clang-format's cursor and ranges have to point into the text it is given, and the
+            // cursor it reports into the text it prints, before the transforms
+            let cursor = find_cursor(&clang_opts);
+            if let Some((at, len, offset)) = cursor {
+                let offset = map_offset(original, &pre_formatted, offset);
+                clang_opts.splice(at..at + len, [format!("-cursor={offset}")]);
+            }
+            for &(start, end) in ranges.iter().flatten() {
+                let start = map_offset(&unix_input, &pre_formatted, start);
+                let end = map_offset(&unix_input, &pre_formatted, end).max(start);
+                clang_opts.push(format!("-offset={start}"));
+                clang_opts.push(format!("-length={}", end - start));
+            }
+            let (out, stderr) =
+                run_clang_format_on_stdin_capture(clang, &clang_opts, timeout, &pre_formatted)?;
+            let (out, cursor) = match split_cursor_header(&out).filter(|_| cursor.is_some()) {
+                Some((offset, rest, code)) => (code.to_string(), Some((offset, rest))),
+                None => (out, None),
+            };
+            // never hand on (or write back) output clang-format mangled
+            check_tokens_kept(&pre_formatted, &out)?;
+            (out, stderr, cursor)
+        }
+        None => (pre_formatted, String::new(), None),
+    };
+    let (output, transforms) = match &ranges {
+        None => post_process(&formatted, config),
+        Some(ranges) => {
+            // only the requested ranges are transformed; the rest is kept exactly as read
+            let mut output = String::new();
+            let mut transforms: Vec<String> = Vec::new();
+            let mut kept_from = 0;
+            for (start, end) in code_spans(&unix_input, ranges) {
+                let from = map_offset(&unix_input, &formatted, start);
+                let to = map_offset(&unix_input, &formatted, end).max(from);
+                let (piece, fired) = post_process(&formatted[from..to], config);
+                output.push_str(&unix_input[kept_from..start]);
+                output.push_str(&piece);
+                for name in fired {
+                    if !transforms.contains(&name) {
+                        transforms.push(name);
+                    }
+                }
+                kept_from = end;
+            }
+            output.push_str(&unix_input[kept_from..]);
+            (output, transforms)
+        }
+    };
+    let mut output = match_trailing_newline(input, output);
+    if crlf {
+        output = output.replace("\r\n", "\n").replace('\n', "\r\n");
+    }
+    output.insert_str(0, bom);
+
+    let cursor = match (cursor, find_cursor(opts)) {
+        (Some((offset, rest)), _) => Some((map_offset(&formatted, &output, offset), rest)),
+        // without clang-format, map the cursor straight from the input
+        (None, Some((_, _, offset))) if clang_format.is_none() => Some((
+            map_offset(original, &output, offset),
+            r#""IncompleteFormat": false }"#.to_string(),
+        )),
+        _ => None,
+    };
+    if let Some((offset, rest)) = &cursor {
+        output.insert_str(0, &format!("{{ \"Cursor\": {offset}, {rest}\n"));
+    }
+    Ok(Formatted {
+        output,
+        stderr,
+        transforms,
+        cursor: cursor.map(|(offset, _)| offset),
+    })
+}
+
+/// Runs the ChucK transforms, then the custom ones from `config`, over clang-format's output.
+fn post_process(formatted: &str, config: &Config) -> (String, Vec<String>) {
+    let (mut output, mut transforms) = apply_transforms_traced(formatted, config);
+    for (i, custom) in config.custom_transforms.iter().enumerate() {
+        let (replaced, _) = outside_off_regions(&output, |code| {
+            let replaced = custom
+                .pattern
+                .replace_all(code, custom.replacement.as_str());
+            (replaced.into_owned(), Vec::new())
+        });
+        if replaced != output {
+            transforms.push(format!("custom_transform[{}]", i + 1));
+            output = replaced;
+        }
+    }
+    (output, transforms)
+}
+
+/// Runs clang-format by sending `input` to stdin, capturing stdout and stderr as Strings.
+///
+/// stdin is written and stdout/stderr drained from separate threads, so large inputs can't
+/// deadlock on full pipe buffers, while this thread waits for clang-format to exit, killing it
+/// once `timeout` has passed.
+///
+/// stderr is returned rather than inherited so callers decide where warnings go; on failure it
+/// is part of [`FmtError::ClangFormatFailed`].
+///
+/// A process is started for every input. Keeping one clang-format running across files isn't
+/// possible: it formats exactly one input per run, reading stdin to EOF, and has no batch or
+/// server mode. Starting it is most of the cost of formatting a typical file (see `cargo
+/// bench`), so many files are formatted in parallel instead.
+fn run_clang_format_on_stdin_capture(
+    clang: &Path,
+    opts: &[String],
+    timeout: Option<Duration>,
+    input: &str,
+) -> Result<(String, String), FmtError> {
+    let io_err = |action| move |source| FmtError::ClangFormatIo { action, source };
+    let no_pipe = || io::Error::other("pipe not available");
+
+    let mut child = Command::new(clang)
+        .args(opts)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(io_err("failed to launch clang-format"))?;
+
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(no_pipe)
+        .map_err(io_err("failed to open clang-format stdin"))?;
+    let input = input.to_owned();
+    // stdin is dropped (closed) when the thread finishes, signalling EOF to clang-format
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+    let mut stdout_pipe = child
+        .stdout
+        .take()
+        .ok_or_else(no_pipe)
+        .map_err(io_err("failed to capture clang-format stdout"))?;
+    let stdout_reader = thread::spawn(move || {
+        let mut out = String::new();
+        stdout_pipe.read_to_string(&mut out).map(|_| out)
+    });
+
+    let mut stderr_pipe = child
+        .stderr
+        .take()
+        .ok_or_else(no_pipe)
+        .map_err(io_err("failed to capture clang-format stderr"))?;
+    let stderr_reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        stderr_pipe.read_to_end(&mut buf).map(|_| buf)
+    });
+
+    let status = match timeout {
+        Some(limit) => wait_with_timeout(&mut child, limit),
+        None => child.wait().map(Some),
+    }
+    .map_err(io_err("failed waiting for clang-format"))?;
+
+    let Some(status) = status else {
+        // Don't join the pipe threads: anything clang-format spawned may still hold the pipes
+        return Err(FmtError::ClangFormatTimedOut(timeout.unwrap_or_default()));
+    };
+
+    let write_result = writer
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("writer thread panicked")))
+        .map_err(io_err("failed writing clang-format stdin"));
+    let read_result = stdout_reader
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("reader thread panicked")))
+        .map_err(io_err("failed reading clang-format stdout"));
+    let stderr = stderr_reader
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("reader thread panicked")))
+        .map(|buf| String::from_utf8_lossy(&buf).into_owned())
+        .map_err(io_err("failed reading clang-format stderr"))?;
+
+    // A failing clang-format usually also breaks the pipe; report the exit status instead
+    if !status.success() {
+        return Err(FmtError::ClangFormatFailed {
+            code: status.code(),
+            stderr,
+        });
+    }
+    write_result?;
+    let out = read_result?;
+
+    Ok((out, stderr))
+}
+
+/// Waits for `child` to exit, killing it after `limit`; `None` means it was killed.
+fn wait_with_timeout(child: &mut Child, limit: Duration) -> io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + limit;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,262 @@
+//! `--lsp`: a minimal language server over stdio answering `textDocument/formatting` and
+//! `textDocument/rangeFormatting` for open documents.
+
+use crate::json::{self, Json};
+use chuckfmt::FmtError;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// LSP `RequestFailed` error code, for documents that could not be formatted.
+const REQUEST_FAILED: i32 = -32803;
+/// JSON-RPC `MethodNotFound` error code.
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// Serves requests from stdin until the client sends `exit`. `format` formats a document's
+/// text given its path (if it is a `file://` URI) and extra clang-format options.
+pub fn serve<F>(format: F) -> Result<(), FmtError>
+where
+    F: Fn(Option<&Path>, &str, &[String]) -> Result<String, FmtError>,
+{
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut output = io::stdout();
+    // open documents by URI, kept in sync through didOpen/didChange/didClose
+    let mut documents: HashMap<String, String> = HashMap::new();
+
+    while let Some(message) = read_message(&mut input).map_err(FmtError::Stdin)? {
+        let Ok(message) = json::parse(&message) else {
+            continue;
+        };
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let uri = params
+            .at(&["textDocument", "uri"])
+            .and_then(Json::as_str)
+            .unwrap_or("");
+
+        let result = match method {
+            "initialize" => Ok(Json::object([
+                (
+                    "capabilities",
+                    Json::object([
+                        // full document sync
+                        ("textDocumentSync", 1.into()),
+                        ("documentFormattingProvider", true.into()),
+                        ("documentRangeFormattingProvider", true.into()),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    Json::object([
+                        ("name", env!("CARGO_PKG_NAME").into()),
+                        ("version", env!("CARGO_PKG_VERSION").into()),
+                    ]),
+                ),
+            ])),
+            "textDocument/didOpen" => {
+                if let Some(text) = params.at(&["textDocument", "text"]).and_then(Json::as_str) {
+                    documents.insert(uri.to_string(), text.to_string());
+                }
+                continue;
+            }
+            "textDocument/didChange" => {
+                let changes = params.get("contentChanges").and_then(Json::as_array);
+                let text = changes
+                    .and_then(|c| c.last())
+                    .and_then(|c| c.get("text"))
+                    .and_then(Json::as_str);
+                if let Some(text) = text {
+                    documents.insert(uri.to_string(), text.to_string());
+                }
+                continue;
+            }
+            "textDocument/didClose" => {
+                documents.remove(uri);
+                continue;
+            }
+            "textDocument/formatting" | "textDocument/rangeFormatting" => {
+                let lines = params
+                    .at(&["range", "start", "line"])
+                    .and_then(Json::as_usize)
+                    .zip(
+                        params
+                            .at(&["range", "end", "line"])
+                            .and_then(Json::as_usize),
+                    );
+                let extra: Vec<String> = lines
+                    .map(|(first, last)| vec![format!("-lines={}:{}", first + 1, last + 1)])
+                    .unwrap_or_default();
+                match documents.get(uri) {
+                    Some(text) => format(uri_to_path(uri).as_deref(), text, &extra)
+                        .map(|formatted| match lines {
+                            Some((first, _)) => clip_to_lines(text, &formatted, first),
+                            None => formatted,
+                        })
+                        .map(|formatted| text_edits(text, &formatted))
+                        .map_err(|e| (REQUEST_FAILED, e.to_string())),
+                    None => Err((REQUEST_FAILED, format!("unknown document: {uri}"))),
+                }
+            }
+            "shutdown" => Ok(Json::Null),
+            "exit" => return Ok(()),
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method: {method}"))),
+        };
+
+        // notifications (no id) get no response
+        let Some(id) = message.get("id") else {
+            continue;
+        };
+        let response = match result {
+            Ok(result) => Json::object([
+                ("jsonrpc", "2.0".into()),
+                ("id", id.clone()),
+                ("result", result),
+            ]),
+            Err((code, message)) => Json::object([
+                ("jsonrpc", "2.0".into()),
+                ("id", id.clone()),
+                (
+                    "error",
+                    Json::object([
+                        ("code", Json::Number(code.into())),
+                        ("message", message.as_str().into()),
+                    ]),
+                ),
+            ]),
+        };
+        write_message(&mut output, &response.to_string()).map_err(FmtError::Stdout)?;
+    }
+    Ok(())
+}
+
+/// Reads one `Content-Length`-framed message, or `None` at end of input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut impl Write, body: &str) -> io::Result<()> {
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+/// The local path of a `file://` URI, percent-decoded.
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let decoded = (b == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match decoded {
+            Some(byte) => {
+                bytes.push(byte);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// Keeps the lines of `old` before line `first` (0-based) in `new`, a range formatting of `old`
+/// starting there. Only the range and the whitespace in front of it are formatted, and that
+/// whitespace may reach back over blank lines before the range, where the client's edit must not
+/// land; the line breaks clang-format left in it are dropped and the range's own first line kept.
+fn clip_to_lines(old: &str, new: &str, first: usize) -> String {
+    let Some(start) = first
+        .checked_sub(1)
+        .and_then(|n| old.match_indices('\n').nth(n))
+        .map(|(i, _)| i + 1)
+    else {
+        return new.to_string();
+    };
+    // everything up to the last code before the range is the same in both
+    let code_end = old[..start].trim_end().len();
+    if !new.is_char_boundary(code_end) || new[..code_end] != old[..code_end] {
+        return new.to_string();
+    }
+    let rest = &new[code_end..];
+    let space = &rest[..rest.len() - rest.trim_start().len()];
+    let mut clipped = old[..start].to_string();
+    let from = match space.rfind('\n') {
+        Some(i) => code_end + i + 1,
+        // clang-format joined the range's first line onto the one before; keep it where it was,
+        // with its indentation
+        None => {
+            let line = &old[start..];
+            clipped.push_str(&line[..line.len() - line.trim_start_matches([' ', '\t']).len()]);
+            code_end + space.len()
+        }
+    };
+    clipped.push_str(&new[from..]);
+    clipped
+}
+
+/// A single `TextEdit` replacing the part of `old` that differs from `new`, or none at all.
+fn text_edits(old: &str, new: &str) -> Json {
+    if old == new {
+        return Json::Array(Vec::new());
+    }
+    let prefix = old
+        .char_indices()
+        .zip(new.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or(old.len().min(new.len()), |((i, _), _)| i);
+    let suffix = old[prefix..]
+        .chars()
+        .rev()
+        .zip(new[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum::<usize>();
+
+    Json::Array(vec![Json::object([
+        (
+            "range",
+            Json::object([
+                ("start", position(old, prefix)),
+                ("end", position(old, old.len() - suffix)),
+            ]),
+        ),
+        ("newText", new[prefix..new.len() - suffix].into()),
+    ])])
+}
+
+/// The LSP position (line, UTF-16 column) of byte `offset` in `text`.
+fn position(text: &str, offset: usize) -> Json {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+    Json::object([
+        ("line", before.matches('\n').count().into()),
+        ("character", character.into()),
+    ])
+}
@@ -1,309 +1,1392 @@
-use lazy_regex::regex_replace_all;
+use chuckfmt::{
+    CONFIG_FILE_NAME, Config, Extensions, FmtError, Language, MIN_CLANG_FORMAT_VERSION,
+    OperatorSpacing, Spacing, apply_column_limit, apply_max_empty_lines, clang_format_version,
+    complete_assume_filename, diagnostics, expand_style_alias, has_assume_filename, markdown,
+    normalize_unicode_operators, parse_major_version, process_string, resolve_clang_format,
+};
+use ignore::Ignore;
+use std::collections::{BTreeMap, HashSet};
 use std::env;
-use std::ffi::OsString;
+use std::ffi::OsStr;
 use std::fs;
-use std::io::{self, Read, Write};
+use std::io::{self, IsTerminal, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
-
-fn apply_pre_formatting_transforms(s: &str) -> String {
-    // add a ";" after @import statements to help clang-format parse them correctly
-    let s = regex_replace_all!(r#"(?m)^(\s*@import\s*\{?\s*".*"\s*?\}?\s*?)$"#, &s, "$1;");
-    s.into_owned()
-}
-
-/// Applies ChucK-specific formatting transforms to the input string.
-fn apply_transforms(s: &str) -> String {
-    // = > -> =>
-    let s = regex_replace_all!(r"=\s*>", &s, "=>");
-    // = < -> =<
-    let s = regex_replace_all!(r"=\s*<", &s, "=<");
-    // @ => -> @=>
-    let s = regex_replace_all!(r"@\s*=>", &s, "@=>");
-    // = ^ -> =^
-    let s = regex_replace_all!(r"=\s*\^\s*", &s, "=^ ");
-    // 1 ::second -> 1::second
-    let s = regex_replace_all!(r"([0-9]+(?:\.[0-9]*)?)\s+::", &s, "$1::");
-    // <<< formatting
-    let s = regex_replace_all!(r"<<<\s*", &s, "<<< ");
-    let s = regex_replace_all!(r"< < <\s*", &s, "<<< ");
-    // >>> formatting
-    let s = regex_replace_all!(r"\s*>>>\s*;", &s, " >>>;");
-    // % ( -> $( for polar literal spacing only
-    let s = regex_replace_all!(r"(?m)(^|[=\(,;\[\{:+\-*/&|^!<>])(\s*)%\s+\(", &s, "$1$2%(");
-    // --> formatting
-    let s = regex_replace_all!(r"\s*-\s*-\s*>\s*", &s, " --> ");
-    // --< formatting
-    let s = regex_replace_all!(r"\s*-\s*-\s*<\s*", &s, " --< ");
-    // - 3.14 -> -3.14 on line starts
-    let s = regex_replace_all!(
-        r"(?m)^(\s*[+-])\s+([A-Za-z_\(\[]|[0-9]+(?:\.[0-9]*)?|\.[0-9]+)",
-        &s,
-        "$1$2"
-    );
-    // spork ~foo -> spork ~ foo
-    let s = regex_replace_all!(r"spork\s*~\s*", &s, "spork ~ ");
-    // 2 *b -> 2 * b
-    let s = regex_replace_all!(
-        r"([A-Za-z0-9_\)\]])\s*(\*)([A-Za-z0-9_\(\[])",
-        &s,
-        "$1 $2 $3"
-    );
+use std::process::Command;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+mod diff;
+mod ignore;
+mod json;
+mod lsp;
+mod watch;
 
-    // remove the ";" we added after @import statements
-    let s = regex_replace_all!(r#"(?m)^(\s*@import.*);$"#, &s, "$1");
-    s.into_owned()
+// -------------------- Main --------------------
+
+fn main() {
+    if let Err(e) = real_main() {
+        eprintln!("{}: {e}", env!("CARGO_PKG_NAME"));
+        std::process::exit(e.exit_code());
+    }
 }
 
-// -------------------- Comment-preserving transform wrapper --------------------
+/// Printed by `--help`; lists only chuckfmt's own options.
+const USAGE: &str = "\
+Usage: chuckfmt [OPTIONS] [CLANG-FORMAT OPTIONS] [FILES or DIRECTORIES...] [-- FILES...]
+
+Formats ChucK source with clang-format, then fixes up ChucK operators.
+Without files, reads stdin (if it isn't a terminal) and writes stdout; a file named - is
+stdin too.
+Options and files may be mixed; everything after -- is a file.
+
+Options:
+  -i                          Format files in place
+  -n, --dry-run               With -i, list the files that would change (with --diff, show how)
+                              and exit 1 if any would, without writing
+  --backup[=SUFFIX]           With -i, keep the original of each changed file as FILE.bak
+                              (or FILE + SUFFIX)
+  --files <LIST>              Read file names from LIST (`-` for stdin), one per line; repeatable.
+                              Lines are source files, never nested lists
+  --check                     List files that would change and exit 1, writing nothing
+  --diff                      Print a unified diff of what would change and exit 1
+  -o, --output <PATH>         Write the result for a single input (or stdin) to PATH
+  --stdin                     Format stdin to stdout; arguments that look like files are ignored
+  --stdin-filename <PATH>     Format stdin as if it were read from PATH
+  --stdin-string <CODE>       Format CODE as if it were read from stdin
+  --range-stdin <START:END>   Only format bytes START..END of stdin, printing the whole buffer
+  --output-format <FORMAT>    text (default) or json: an array of {path, changed, formatted}
+  --lsp                       Run a language server on stdio (formatting, rangeFormatting)
+  --watch                     Format the given files (and ChucK files under directories) in place
+                              whenever they change, until interrupted
+  --staged                    Format the files staged in git in place and stage the result
+  --quiet                     With -i, don't print how many files were changed
+  --extension <EXT>           Extension to look for in directories; repeatable
+                              [default: ck and chuck, or `extensions` in .chuckfmt.toml]
+  --jobs <N>                  Files to format in parallel [default: number of CPUs]
+  --unique                    Format each file once, even if it is given several times
+  --no-ignore                 Walk directories without honoring .gitignore/.ignore files
+  --only-ck                   Skip (with a notice) files without one of those extensions
+  --markdown                  Format the ```chuck (or ```ck) code blocks of Markdown files,
+                              leaving the rest as is; directories are searched for .md files
+  --lossy                     Replace invalid UTF-8 in sources with U+FFFD instead of failing
+  --normalize-unicode-operators
+                              Replace Unicode arrows with ASCII operators (⇒ → =>, ≤ → <=, ...)
+  --style=chuck               Use the built-in ChucK clang-format style
+  --column-limit <N>          Set clang-format's ColumnLimit on top of the style in use
+  --keep-blank-lines <N>      Keep up to N blank lines in a row (clang-format's MaxEmptyLinesToKeep)
+  --assume-language <LANG>    Have clang-format treat ChucK as java (default) or cpp
+  --spacing <FAMILY=STYLE,..> Space operators (spaced, default) or not (tight), per family:
+                              chuck, upchuck, print, spork; overrides [spacing] in .chuckfmt.toml
+  --no-clang-format           Only apply the ChucK transforms, without clang-format
+  --transforms-only           Like --no-clang-format, but also keep wrapped => chains as they are
+                              laid out even with [chain] reflow = true, so only the operators
+                              themselves change
+  --clang-format-path <PATH>  clang-format binary to use
+  --timeout <SECONDS>         Kill clang-format if it runs longer than this per file
+  --require-version           Fail if clang-format is older than the supported minimum
+  --check-install             Check that clang-format is found and round-trips ChucK, then exit
+  --fail-on-warnings          Fail a file (and the run) if clang-format prints warnings for it
+  --warn-suspicious           Warn, with line numbers, about output clang-format likely misread
+                              (an unbalanced <<< or >>>, a => cut off by a statement boundary)
+  --verbose                   Log each file's clang-format command and the transforms that fired
+  --print-clang-command       Print the exact clang-format command (shell-quoted) for each file
+  -V, --version               Print version information
+  -h, --help                  Print this help
+
+Any other option is passed through to clang-format (see `clang-format --help`).
+
+Exit status: 0 on success, 1 if formatting failed or (with --check, --diff or -n) files would
+change, 2 for invalid or conflicting arguments.
+";
+
+/// Runs the command line. chuckfmt's own flags (see [`USAGE`]) are taken out of the arguments
+/// first; what is left is split into clang-format options and files, at `--` if there is one and
+/// otherwise by what looks like an option. Each input is then formatted with those options (plus
+/// an `--assume-filename` naming it as Java, unless one was given) and the ChucK transforms, and
+/// the result goes to stdout, back into the file (`-i`), or into a report (`--check`, `--diff`,
+/// `--output-format=json`), depending on the mode.
+fn real_main() -> Result<(), FmtError> {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if take_flag(&mut args, "--help") | take_flag(&mut args, "-h") {
+        print!("{USAGE}");
+        return Ok(());
+    }
+    let check = take_flag(&mut args, "--check");
+    let diff = take_flag(&mut args, "--diff");
+    let extensions = take_values(&mut args, &["--extension"])?;
+    let markdown = take_flag(&mut args, "--markdown");
+    // with --markdown, directory walks look for Markdown files unless --extension says otherwise
+    let extensions = if extensions.is_empty() {
+        markdown.then(|| Extensions::new(&["md", "markdown"]).unwrap())
+    } else {
+        let extensions = Extensions::new(&extensions)
+            .ok_or_else(|| FmtError::Usage("--extension requires a non-empty value".to_string()))?;
+        Some(extensions)
+    };
+    let jobs = match take_value(&mut args, "--jobs")? {
+        Some(n) => n
+            .parse::<usize>()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| FmtError::Usage(format!("invalid --jobs value: {n}")))?,
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    let timeout = match take_value(&mut args, "--timeout")? {
+        Some(t) => Some(
+            t.parse::<f64>()
+                .ok()
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                .filter(|d| !d.is_zero())
+                .ok_or_else(|| FmtError::Usage(format!("invalid --timeout value: {t}")))?,
+        ),
+        None => None,
+    };
+    let unique = take_flag(&mut args, "--unique");
+    let lsp = take_flag(&mut args, "--lsp");
+    let watch = take_flag(&mut args, "--watch");
+    let staged = take_flag(&mut args, "--staged");
+    let quiet = take_flag(&mut args, "--quiet");
+    let dry_run = take_flag(&mut args, "--dry-run") | take_flag(&mut args, "-n");
+    let no_ignore = take_flag(&mut args, "--no-ignore");
+    let only_ck = take_flag(&mut args, "--only-ck");
+    let range_stdin = match take_value(&mut args, "--range-stdin")? {
+        Some(range) => Some(parse_byte_range(&range).ok_or_else(|| {
+            FmtError::Usage(format!(
+                "invalid --range-stdin value: {range} (expected START:END byte offsets)"
+            ))
+        })?),
+        None => None,
+    };
+    let column_limit = match take_value(&mut args, "--column-limit")? {
+        Some(n) => Some(
+            n.parse::<u32>()
+                .map_err(|_| FmtError::Usage(format!("invalid --column-limit value: {n}")))?,
+        ),
+        None => None,
+    };
+    let keep_blank_lines = match take_value(&mut args, "--keep-blank-lines")? {
+        Some(n) => Some(
+            n.parse::<u32>()
+                .map_err(|_| FmtError::Usage(format!("invalid --keep-blank-lines value: {n}")))?,
+        ),
+        None => None,
+    };
+    let json = match take_value(&mut args, "--output-format")?.as_deref() {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(other) => {
+            return Err(FmtError::Usage(format!(
+                "invalid --output-format value: {other} (expected text or json)"
+            )));
+        }
+    };
+    let assume_language = match take_value(&mut args, "--assume-language")? {
+        Some(name) => Some(Language::parse(&name).ok_or_else(|| {
+            FmtError::Usage(format!(
+                "invalid --assume-language value: {name} (expected java or cpp)"
+            ))
+        })?),
+        None => None,
+    };
+    let spacing = match take_value(&mut args, "--spacing")? {
+        Some(families) => parse_spacing(&families)?,
+        None => Vec::new(),
+    };
+    let lossy = take_flag(&mut args, "--lossy");
+    let normalize_unicode = take_flag(&mut args, "--normalize-unicode-operators");
+    let backup = take_optional_value(&mut args, "--backup").map(|s| s.unwrap_or(".bak".into()));
+    let transforms_only = take_flag(&mut args, "--transforms-only");
+    let no_clang_format = take_flag(&mut args, "--no-clang-format") | transforms_only;
+    let verbose = take_flag(&mut args, "--verbose");
+    let fail_on_warnings = take_flag(&mut args, "--fail-on-warnings");
+    let print_clang_command = take_flag(&mut args, "--print-clang-command");
+    let warn_suspicious = take_flag(&mut args, "--warn-suspicious");
+    let clang_format_path = take_value(&mut args, "--clang-format-path")?.map(PathBuf::from);
+    if take_flag(&mut args, "--version") | take_flag(&mut args, "-V") {
+        print_version(clang_format_path.as_deref());
+        return Ok(());
+    }
+    if take_flag(&mut args, "--check-install") {
+        return check_install(clang_format_path.as_deref());
+    }
+    let require_version = take_flag(&mut args, "--require-version");
+    let stdin_filename = take_value(&mut args, "--stdin-filename")?.map(PathBuf::from);
+    let stdin_string = take_value(&mut args, "--stdin-string")?;
+    let stdin_only = take_flag(&mut args, "--stdin");
+    let output = take_value(&mut args, "--output")?;
+    let output = take_value(&mut args, "-o")?.or(output).map(PathBuf::from);
+    let clang_format = if no_clang_format {
+        None
+    } else {
+        Some(resolve_clang_format(clang_format_path.as_deref())?)
+    };
+    if let Some(clang) = &clang_format {
+        check_clang_format_version(clang, require_version)?;
+    }
+    let mut options = Options {
+        clang_format,
+        timeout,
+        jobs,
+        stdin_filename,
+        stdin_string: None,
+        lossy,
+        extensions,
+        assume_language,
+        spacing,
+        transforms_only,
+        normalize_unicode,
+        verbose,
+        fail_on_warnings,
+        print_clang_command,
+        warn_suspicious,
+        markdown,
+        listed_at: BTreeMap::new(),
+    };
+
+    let has_inplace = staged || args.iter().any(|a| a == "-i");
+    if check && has_inplace {
+        return Err(FmtError::Usage(
+            "--check cannot be combined with -i".to_string(),
+        ));
+    }
+    if dry_run && !has_inplace {
+        return Err(FmtError::Usage("--dry-run requires -i".to_string()));
+    }
+    if diff && has_inplace && !dry_run {
+        return Err(FmtError::Usage(
+            "--diff cannot be combined with -i".to_string(),
+        ));
+    }
 
-/// Segment types for parsing: code that should be transformed vs comments that should be preserved.
-#[derive(Debug)]
-enum Segment {
-    Code(String),
-    Comment(String),
-}
+    if json && (has_inplace || check || diff || output.is_some()) {
+        return Err(FmtError::Usage(
+            "--output-format=json cannot be combined with -i, --check, --diff or -o".to_string(),
+        ));
+    }
+    if lsp && (has_inplace || check || diff || json || output.is_some()) {
+        return Err(FmtError::Usage(
+            "--lsp cannot be combined with -i, --check, --diff, --output-format or -o".to_string(),
+        ));
+    }
+    if staged && (check || diff || json || lsp || output.is_some()) {
+        return Err(FmtError::Usage(
+            "--staged cannot be combined with --check, --diff, --output-format, --lsp or -o"
+                .to_string(),
+        ));
+    }
+    if watch && (check || diff || json || lsp || staged || dry_run || output.is_some()) {
+        return Err(FmtError::Usage(
+            "--watch cannot be combined with --check, --diff, --output-format, --lsp, --staged, \
+             -n or -o"
+                .to_string(),
+        ));
+    }
+    if backup.is_some() && !has_inplace && !watch {
+        return Err(FmtError::Usage("--backup requires -i".to_string()));
+    }
+    if output.is_some() && (has_inplace || check || diff) {
+        return Err(FmtError::Usage(
+            "-o/--output cannot be combined with -i, --check or --diff".to_string(),
+        ));
+    }
 
-/// Parses input into segments of code and comments.
-/// Handles // line comments, /* */ block comments, and string/char literals correctly.
-fn parse_segments(input: &str) -> Vec<Segment> {
-    let mut segments = Vec::new();
-    let mut current_code = String::new();
-    let chars: Vec<char> = input.chars().collect();
-    let len = chars.len();
-    let mut i = 0;
+    if stdin_only && (has_inplace || lsp || watch || stdin_string.is_some()) {
+        return Err(FmtError::Usage(
+            "--stdin cannot be combined with -i, --staged, --lsp, --watch or --stdin-string"
+                .to_string(),
+        ));
+    }
 
-    while i < len {
-        // Check for string literal
-        if chars[i] == '"' {
-            current_code.push(chars[i]);
-            i += 1;
-            while i < len {
-                if chars[i] == '\\' && i + 1 < len {
-                    current_code.push(chars[i]);
-                    current_code.push(chars[i + 1]);
-                    i += 2;
-                } else if chars[i] == '"' {
-                    current_code.push(chars[i]);
-                    i += 1;
-                    break;
-                } else {
-                    current_code.push(chars[i]);
-                    i += 1;
-                }
+    let (mut opts, mut files) = split_opts_files(&args)?;
+    if stdin_only {
+        if args.iter().skip_while(|a| *a != "--").any(|a| a != "--") {
+            return Err(FmtError::Usage(
+                "--stdin reads stdin and takes no files".to_string(),
+            ));
+        }
+        // whatever the heuristic took for a file is dropped rather than formatted
+        for f in files.drain(..) {
+            log(
+                &f.display().to_string(),
+                "ignored: --stdin formats stdin only",
+            );
+        }
+    }
+    let list_from_stdin = expand_files_from_list(&mut opts, &mut files, &mut options.listed_at)?;
+    if stdin_only && !files.is_empty() {
+        return Err(FmtError::Usage(
+            "--stdin cannot be combined with --files".to_string(),
+        ));
+    }
+    // --watch walks the directories again when an event names a new file
+    let watch_roots = files.clone();
+    expand_directories(&options, &mut files, !no_ignore)?;
+    if unique {
+        dedup_files(&mut files);
+    }
+    if only_ck && !files.is_empty() {
+        let mut kept = Vec::with_capacity(files.len());
+        for f in files {
+            let extensions = recognized_extensions(&options, f.parent().unwrap_or(Path::new("")))?;
+            if is_stdin(&f) || extensions.matches(&f) {
+                kept.push(f);
+            } else {
+                let names: Vec<String> = extensions
+                    .as_slice()
+                    .iter()
+                    .map(|e| format!(".{e}"))
+                    .collect();
+                log(
+                    &f.display().to_string(),
+                    &format!("skipped: not a {} file", names.join(" or ")),
+                );
             }
-            continue;
         }
+        files = kept;
+        if files.is_empty() {
+            return Ok(());
+        }
+    }
+    if staged {
+        if !files.is_empty() {
+            return Err(FmtError::Usage(
+                "--staged formats the files staged in git and takes no others".to_string(),
+            ));
+        }
+        files = staged_files(&options)?;
+        if files.is_empty() {
+            return Ok(());
+        }
+    }
+    if let Some(code) = stdin_string {
+        if !files.is_empty() || list_from_stdin || has_inplace {
+            return Err(FmtError::Usage(
+                "--stdin-string takes the place of stdin and can't be combined with files or -i"
+                    .to_string(),
+            ));
+        }
+        // a one-liner from the shell comes without the newline a source file would end in
+        let code = if code.ends_with('\n') {
+            code
+        } else {
+            code + "\n"
+        };
+        options.stdin_string = Some(code);
+    }
+    if list_from_stdin && files.is_empty() {
+        // stdin held the (empty) file list, not source to format
+        return Ok(());
+    }
+    let stdin_inputs = files.iter().filter(|f| is_stdin(f)).count();
+    if stdin_inputs > 1 || (stdin_inputs == 1 && list_from_stdin) {
+        return Err(FmtError::Usage(
+            "stdin (-) can only be read once".to_string(),
+        ));
+    }
+    if stdin_inputs == 1 && has_inplace {
+        return Err(FmtError::Usage(
+            "-i can't write back to stdin (-); format it to stdout instead".to_string(),
+        ));
+    }
+    if let Some((start, end)) = range_stdin {
+        if has_inplace || files.iter().any(|f| !is_stdin(f)) {
+            return Err(FmtError::Usage(
+                "--range-stdin only applies when formatting stdin to stdout".to_string(),
+            ));
+        }
+        // formatted like clang-format's own byte range options
+        opts.push(format!("-offset={start}"));
+        opts.push(format!("-length={}", end - start));
+    }
+    if options.normalize_unicode && has_byte_offsets(&opts) {
+        // the offsets would point into the text before its glyphs were replaced
+        return Err(FmtError::Usage(
+            "--normalize-unicode-operators can't be combined with byte offsets \
+             (-offset, -length, -cursor, --range-stdin); use -lines instead"
+                .to_string(),
+        ));
+    }
+    if options.markdown && (has_byte_offsets(&opts) || has_lines(&opts)) {
+        return Err(FmtError::Usage(
+            "--markdown formats whole code blocks and can't be combined with -lines, -offset, \
+             -length, -cursor or --range-stdin"
+                .to_string(),
+        ));
+    }
+    if output.is_some() && files.len() > 1 {
+        return Err(FmtError::Usage(format!(
+            "-o/--output needs a single input file, got {}",
+            files.len()
+        )));
+    }
+    expand_style_alias(&mut opts);
+    if let Some(limit) = column_limit {
+        apply_column_limit(&mut opts, limit)?;
+    }
+    if let Some(keep) = keep_blank_lines {
+        apply_max_empty_lines(&mut opts, keep)?;
+    }
 
-        // Check for char literal
-        if chars[i] == '\'' {
-            current_code.push(chars[i]);
-            i += 1;
-            while i < len {
-                if chars[i] == '\\' && i + 1 < len {
-                    current_code.push(chars[i]);
-                    current_code.push(chars[i + 1]);
-                    i += 2;
-                } else if chars[i] == '\'' {
-                    current_code.push(chars[i]);
-                    i += 1;
-                    break;
-                } else {
-                    current_code.push(chars[i]);
-                    i += 1;
-                }
-            }
-            continue;
+    if lsp {
+        if !files.is_empty() {
+            return Err(FmtError::Usage(
+                "--lsp reads documents from the client, not files".to_string(),
+            ));
         }
+        return lsp::serve(|path, text, extra| {
+            let opts = [opts.as_slice(), extra].concat();
+            format_source(&options, &opts, path, text)
+        });
+    }
 
-        // Check for line comment
-        if i + 1 < len && chars[i] == '/' && chars[i + 1] == '/' {
-            // Flush current code
-            if !current_code.is_empty() {
-                segments.push(Segment::Code(std::mem::take(&mut current_code)));
-            }
-            let mut comment = String::new();
-            comment.push(chars[i]);
-            comment.push(chars[i + 1]);
-            i += 2;
-            while i < len && chars[i] != '\n' {
-                comment.push(chars[i]);
-                i += 1;
-            }
-            // Include the newline in the comment
-            if i < len && chars[i] == '\n' {
-                comment.push(chars[i]);
-                i += 1;
-            }
-            segments.push(Segment::Comment(comment));
-            continue;
+    if watch {
+        if watch_roots.is_empty() || stdin_inputs > 0 {
+            return Err(FmtError::Usage(
+                "--watch needs files or directories to watch (not stdin)".to_string(),
+            ));
+        }
+        let opts: Vec<String> = opts.into_iter().filter(|o| o != "-i").collect();
+        return watch::run(
+            &watch_roots,
+            || {
+                let mut files = watch_roots.clone();
+                expand_directories(&options, &mut files, !no_ignore)?;
+                Ok(files)
+            },
+            |f| format_in_place(&options, &opts, f, backup.as_deref()),
+        );
+    }
+
+    // reading a terminal would just wait for input the user may not know is expected
+    if files.is_empty()
+        && !list_from_stdin
+        && !stdin_only
+        && options.stdin_string.is_none()
+        && io::stdin().is_terminal()
+    {
+        return Err(FmtError::Usage(
+            "no files given and stdin is a terminal: pass files or directories, pipe ChucK \
+             source in, or give - to type it (see --help)"
+                .to_string(),
+        ));
+    }
+
+    if dry_run {
+        if files.is_empty() {
+            return Err(FmtError::Usage("-i requires at least one file".to_string()));
         }
+        // everything -i would do, short of writing: list (or diff) the files that would change
+        let opts: Vec<String> = opts.into_iter().filter(|o| o != "-i").collect();
+        return check_files(&options, &opts, &files, diff);
+    }
+
+    if check || diff {
+        return check_files(&options, &opts, &files, diff);
+    }
+
+    if json {
+        return json_report(&options, &opts, &files);
+    }
+
+    if !has_inplace {
+        // If no files detected, behave like clang-format: stdin -> stdout
+        if files.is_empty() {
+            let input = read_stdin(&options)?;
+
+            let stdin_filename = options.stdin_filename.as_deref();
+            let fixed = format_source(&options, &opts, stdin_filename, &input)?;
 
-        // Check for block comment
-        if i + 1 < len && chars[i] == '/' && chars[i + 1] == '*' {
-            // Flush current code
-            if !current_code.is_empty() {
-                segments.push(Segment::Code(std::mem::take(&mut current_code)));
+            if let Some(out) = &output {
+                return write_atomic(out, &fixed);
             }
-            let mut comment = String::new();
-            comment.push(chars[i]);
-            comment.push(chars[i + 1]);
-            i += 2;
-            while i < len {
-                if i + 1 < len && chars[i] == '*' && chars[i + 1] == '/' {
-                    comment.push(chars[i]);
-                    comment.push(chars[i + 1]);
-                    i += 2;
-                    break;
-                } else {
-                    comment.push(chars[i]);
-                    i += 1;
-                }
+            io::stdout()
+                .write_all(fixed.as_bytes())
+                .map_err(FmtError::Stdout)?;
+            return Ok(());
+        }
+
+        if let Some(out) = &output {
+            let fixed = format_source(
+                &options,
+                &opts,
+                source_path(&options, &files[0]),
+                &read_source(&options, &files[0])?,
+            )?;
+            return write_atomic(out, &fixed);
+        }
+
+        // Files provided: format each file via stdin and write to stdout, in the order given
+        let results = par_map(&files, options.jobs, |f| {
+            let path = source_path(&options, f);
+            format_source(&options, &opts, path, &read_source(&options, f)?)
+        });
+        let mut out = io::stdout();
+        let mut errors = Vec::new();
+        for fixed in results {
+            match fixed {
+                Ok(fixed) => out.write_all(fixed.as_bytes()).map_err(FmtError::Stdout)?,
+                Err(e) => errors.push(e),
             }
-            segments.push(Segment::Comment(comment));
-            continue;
         }
+        return report_failures(errors, files.len());
+    }
 
-        // Regular character
-        current_code.push(chars[i]);
-        i += 1;
+    // In-place mode: require at least one file
+    if files.is_empty() {
+        return Err(FmtError::Usage("-i requires at least one file".to_string()));
     }
 
-    // Flush remaining code
-    if !current_code.is_empty() {
-        segments.push(Segment::Code(current_code));
+    // Remove -i from options for the stdin formatting path
+    let opts_no_i: Vec<String> = opts.into_iter().filter(|o| o != "-i").collect();
+
+    let results = par_map(&files, options.jobs, |f| {
+        format_in_place(&options, &opts_no_i, f, backup.as_deref())
+    });
+
+    let changed: Vec<&PathBuf> = files
+        .iter()
+        .zip(&results)
+        .filter(|(_, r)| matches!(r, Ok(true)))
+        .map(|(f, _)| f)
+        .collect();
+    // on stderr, so it never mixes with formatted output
+    if !quiet {
+        let total = files.len();
+        let noun = if total == 1 { "file" } else { "files" };
+        eprintln!(
+            "{}: formatted {} of {total} {noun}",
+            env!("CARGO_PKG_NAME"),
+            changed.len()
+        );
+    }
+    if staged && !changed.is_empty() {
+        let mut add = vec![OsStr::new("add"), OsStr::new("--")];
+        add.extend(changed.iter().map(|f| f.as_os_str()));
+        git(&add)?;
     }
 
-    segments
+    report_failures(
+        results.into_iter().filter_map(Result::err).collect(),
+        files.len(),
+    )
 }
 
-/// Applies transforms only to code segments, preserving comments unchanged.
-fn apply_transforms_preserving_comments(input: &str) -> String {
-    let segments = parse_segments(input);
-    let mut result = String::new();
+/// What the command line says about reading and formatting sources, beyond the options passed
+/// on to clang-format. Built once by `real_main` and handed to everything that formats.
+struct Options {
+    /// `None` with `--no-clang-format`: only the ChucK transforms run.
+    clang_format: Option<PathBuf>,
+    /// `--timeout`: kill clang-format if it runs longer than this per source.
+    timeout: Option<Duration>,
+    /// `--jobs`: sources formatted in parallel.
+    jobs: usize,
+    /// `--stdin-filename`: the path stdin is formatted as.
+    stdin_filename: Option<PathBuf>,
+    /// `--stdin-string`: the code read in place of stdin.
+    stdin_string: Option<String>,
+    /// `--lossy`: decode invalid UTF-8 in sources as U+FFFD instead of failing.
+    lossy: bool,
+    /// `--extension`: overrides the `extensions` of every `.chuckfmt.toml`.
+    extensions: Option<Extensions>,
+    /// `--assume-language`: overrides the `language` of every `.chuckfmt.toml`.
+    assume_language: Option<Language>,
+    /// `--spacing`: overrides the `[spacing]` of every `.chuckfmt.toml`, family by family.
+    spacing: Vec<(&'static str, OperatorSpacing)>,
+    /// `--transforms-only`: no clang-format and no chain reflow, so the layout is kept.
+    transforms_only: bool,
+    /// `--normalize-unicode-operators`: replace Unicode arrows with ASCII operators first.
+    normalize_unicode: bool,
+    /// `--verbose`: log each source's clang-format command line and the transforms that fired.
+    verbose: bool,
+    /// `--fail-on-warnings`: fail a source if clang-format wrote anything to stderr for it.
+    fail_on_warnings: bool,
+    /// `--print-clang-command`: log the exact clang-format command line for each source.
+    print_clang_command: bool,
+    /// `--warn-suspicious`: report spots in each output clang-format likely misread.
+    warn_suspicious: bool,
+    /// `--markdown`: sources are Markdown, and only their ChucK code blocks are formatted.
+    markdown: bool,
+    /// Where each file from a `--files` list was listed (list, 1-based line), so a file that
+    /// can't be read is reported against the line that named it.
+    listed_at: BTreeMap<PathBuf, (PathBuf, usize)>,
+}
 
-    for segment in segments {
-        match segment {
-            Segment::Code(code) => result.push_str(&apply_transforms(&code)),
-            Segment::Comment(comment) => result.push_str(&comment),
+/// Formats file `f` in place (first saving its original as `f` + `backup`, if given), returning
+/// whether it changed. A file that is already formatted isn't written, so its mtime stays.
+fn format_in_place(
+    options: &Options,
+    opts: &[String],
+    f: &Path,
+    backup: Option<&str>,
+) -> Result<bool, FmtError> {
+    let bytes = read_file(options, f)?;
+    // the backup gets the bytes as read, even where `--lossy` replaced some in `input`
+    let input = decode_source(options, bytes.clone(), f)?;
+    let fixed = format_source(options, opts, Some(f), &input)?;
+    if fixed == input {
+        return Ok(false);
+    }
+    if let Some(suffix) = backup {
+        let mut backup_path = f.as_os_str().to_owned();
+        backup_path.push(suffix);
+        write_atomic(Path::new(&backup_path), &bytes)?;
+    }
+    write_atomic(f, &fixed).map(|_| true)
+}
+
+// -------------------- git (--staged) --------------------
+
+/// Files staged for commit (added, copied or modified) with a recognized extension.
+fn staged_files(options: &Options) -> Result<Vec<PathBuf>, FmtError> {
+    // `git diff` names files relative to the top of the work tree
+    let top = git(&[OsStr::new("rev-parse"), OsStr::new("--show-toplevel")])?;
+    let top = Path::new(top.trim_end_matches('\n'));
+    let names =
+        git(&["diff", "--cached", "--name-only", "--diff-filter=ACM", "-z"].map(OsStr::new))?;
+    let mut files = Vec::new();
+    for path in names
+        .split('\0')
+        .filter(|name| !name.is_empty())
+        .map(|name| top.join(name))
+    {
+        if recognized_extensions(options, path.parent().unwrap_or(top))?.matches(&path) {
+            files.push(path);
         }
     }
+    Ok(files)
+}
 
-    result
+/// Runs git with `args`, returning its stdout.
+fn git(args: &[&OsStr]) -> Result<String, FmtError> {
+    let command = format!(
+        "git {}",
+        args.iter()
+            .map(|a| a.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    let out = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| FmtError::Git {
+            command: command.clone(),
+            message: e.to_string(),
+        })?;
+    if !out.status.success() {
+        return Err(FmtError::Git {
+            command,
+            message: String::from_utf8_lossy(&out.stderr).trim().to_string(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
 }
 
-// -------------------- Main --------------------
+/// Formats each file (or stdin) and prints one JSON array describing the results:
+/// `{"path", "changed", "formatted"}` per file, or `{"path", "error"}` if it failed.
+fn json_report(options: &Options, opts: &[String], files: &[PathBuf]) -> Result<(), FmtError> {
+    let format = |path: Option<&Path>, input: Result<String, FmtError>| -> Result<_, FmtError> {
+        let input = input?;
+        let fixed = format_source(options, opts, path, &input)?;
+        Ok((fixed != input, fixed))
+    };
+    let (paths, results) = if files.is_empty() {
+        let stdin_filename = options.stdin_filename.as_deref();
+        (
+            vec![stdin_filename],
+            vec![format(stdin_filename, read_stdin(options))],
+        )
+    } else {
+        (
+            files.iter().map(|f| source_path(options, f)).collect(),
+            par_map(files, options.jobs, |f| {
+                format(source_path(options, f), read_source(options, f))
+            }),
+        )
+    };
+
+    let mut out = String::from("[");
+    let mut failed = 0;
+    for (i, (path, result)) in paths.into_iter().zip(results).enumerate() {
+        out.push_str(if i == 0 { "\n  " } else { ",\n  " });
+        let path = path.map_or("null".to_string(), |p| {
+            json::quote(&p.display().to_string())
+        });
+        match result {
+            Ok((changed, formatted)) => out.push_str(&format!(
+                "{{\"path\": {path}, \"changed\": {changed}, \"formatted\": {}}}",
+                json::quote(&formatted)
+            )),
+            Err(e) => {
+                out.push_str(&format!(
+                    "{{\"path\": {path}, \"error\": {}}}",
+                    json::quote(&e.to_string())
+                ));
+                failed += 1;
+            }
+        }
+    }
+    out.push_str("\n]\n");
+    io::stdout()
+        .write_all(out.as_bytes())
+        .map_err(FmtError::Stdout)?;
+
+    // The errors are in the JSON already; only the exit status is left to set
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(FmtError::FilesFailed {
+            failed,
+            total: files.len().max(1),
+        })
+    }
+}
 
-fn main() {
-    if let Err(e) = real_main() {
-        eprintln!("{}: {e}", env!("CARGO_PKG_NAME"));
-        std::process::exit(1);
+/// Reports the errors of a multi-file run, which carries on past failing files. A single error
+/// is returned as is; several are printed and summarized.
+fn report_failures(mut errors: Vec<FmtError>, total: usize) -> Result<(), FmtError> {
+    match errors.len() {
+        0 => Ok(()),
+        1 if total == 1 => Err(errors.remove(0)),
+        failed => {
+            for e in &errors {
+                eprintln!("{}: {e}", env!("CARGO_PKG_NAME"));
+            }
+            Err(FmtError::FilesFailed { failed, total })
+        }
     }
 }
 
-/// Matches your bash wrapper behavior:
-/// - Parse args into opts + files (supports `--` delimiter; heuristic otherwise)
-/// - If user didn't provide assume-filename, append `--assume-filename=code.java`
-/// - Without `-i`:
-///   - If no files: read stdin, run clang-format on stdin, transforms, stdout
-///   - If files: for each file, run clang-format on stdin (file contents), transforms, stdout
-/// - With `-i`:
-///   - Requires at least one file
-///   - For each file: run clang-format on stdin (file contents) with opts (minus -i), transforms, overwrite file
-fn real_main() -> Result<(), String> {
-    let args: Vec<String> = env::args().skip(1).collect();
-    let clang_format = resolve_clang_format()?;
+/// Prints chuckfmt's version and the clang-format it would use, for bug reports.
+fn print_version(clang_format_path: Option<&Path>) {
+    println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    let clang = match resolve_clang_format(clang_format_path) {
+        Ok(clang) => clang,
+        Err(FmtError::ClangFormatNotFound) => return println!("clang-format: not found"),
+        Err(e) => return println!("clang-format: {e}"),
+    };
+    match clang_format_version(&clang) {
+        Ok(version) => println!("clang-format: {} ({version})", clang.display()),
+        Err(e) => println!("clang-format: {} ({e})", clang.display()),
+    }
+}
 
-    let has_inplace = args.iter().any(|a| a == "-i");
+/// ChucK that `--check-install` expects to come back from the whole pipeline unchanged.
+const INSTALL_CHECK_SNIPPET: &str = "\
+SinOsc s => Gain g => dac;
+0.5 => g.gain;
+1::second => now;
+<<< \"ok\", s.freq() >>>;
+";
+
+/// Checks that clang-format can be found, is recent enough, and round-trips a ChucK snippet
+/// through the full pipeline, printing each step's result.
+fn check_install(clang_format_path: Option<&Path>) -> Result<(), FmtError> {
+    let step = |name: &str, result: &str| println!("{name:<13} {result}");
+    // the details go on stdout with the step; the error only names the step that failed
+    let fail = |name: &str, e: FmtError| {
+        step(name, &format!("FAIL: {e}"));
+        FmtError::InstallCheckFailed(format!("{name} step failed"))
+    };
+    println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+
+    let clang = resolve_clang_format(clang_format_path).map_err(|e| fail("clang-format", e))?;
+    step("clang-format", &format!("ok: {}", clang.display()));
+
+    let version = clang_format_version(&clang).map_err(|e| fail("version", e))?;
+    match parse_major_version(&version) {
+        Some(found) if found < MIN_CLANG_FORMAT_VERSION => step(
+            "version",
+            &format!(
+                "warning: {version} is older than {MIN_CLANG_FORMAT_VERSION}; formatting may differ"
+            ),
+        ),
+        Some(_) => step("version", &format!("ok: {version}")),
+        None => step(
+            "version",
+            &format!("warning: can't tell the version from '{version}'"),
+        ),
+    }
 
-    let (mut opts, mut files) = split_opts_files(&args);
-    expand_files_from_list(&opts, &mut files)?;
+    // the built-in style, so no .clang-format around changes the expected layout
+    let mut opts = vec!["--style=chuck".to_string()];
+    expand_style_alias(&mut opts);
+    opts.push(format!(
+        "--assume-filename={}",
+        assume_filename(None, Language::Java, None)
+    ));
+    let formatted = process_string(
+        Some(&clang),
+        &opts,
+        None,
+        &Config::default(),
+        INSTALL_CHECK_SNIPPET,
+    )
+    .map_err(|e| fail("round trip", e))?;
+    if formatted.output != INSTALL_CHECK_SNIPPET {
+        step(
+            "round trip",
+            "FAIL: the ChucK operators didn't come back intact:",
+        );
+        print!(
+            "{}",
+            diff::unified_diff(
+                INSTALL_CHECK_SNIPPET,
+                &formatted.output,
+                "expected",
+                "formatted"
+            )
+        );
+        return Err(FmtError::InstallCheckFailed(
+            "round trip step failed".to_string(),
+        ));
+    }
+    step("round trip", "ok");
+    println!("chuckfmt is ready to use");
+    Ok(())
+}
 
-    if !has_assume_filename(&opts) {
-        opts.push("--assume-filename=code.java".to_string());
+/// Warns (or, with `require`, fails) if clang-format is older than the tested minimum, since
+/// older versions lay out Java differently enough to change how ChucK round-trips.
+fn check_clang_format_version(clang: &Path, require: bool) -> Result<(), FmtError> {
+    let version = clang_format_version(clang)?;
+    let Some(found) = parse_major_version(&version) else {
+        eprintln!(
+            "{}: warning: could not determine clang-format version from '{version}'",
+            env!("CARGO_PKG_NAME")
+        );
+        return Ok(());
+    };
+    if found < MIN_CLANG_FORMAT_VERSION {
+        let err = FmtError::ClangFormatTooOld {
+            found,
+            minimum: MIN_CLANG_FORMAT_VERSION,
+        };
+        if require {
+            return Err(err);
+        }
+        eprintln!(
+            "{}: warning: {err}; formatting may differ",
+            env!("CARGO_PKG_NAME")
+        );
     }
+    Ok(())
+}
 
-    if !has_inplace {
-        // If no files detected, behave like clang-format: stdin -> stdout
-        if files.is_empty() {
-            let mut input = String::new();
-            io::stdin()
-                .read_to_string(&mut input)
-                .map_err(|e| format!("failed to read stdin: {e}"))?;
+/// Reads a source file, reporting failures against its path; `-` reads stdin.
+fn read_source(options: &Options, f: &Path) -> Result<String, FmtError> {
+    if is_stdin(f) {
+        return read_stdin(options);
+    }
+    let bytes = read_file(options, f)?;
+    decode_source(options, bytes, f)
+}
 
-            let fixed = process_string(&clang_format, &opts, &input)?;
+/// The raw contents of the listed file `f`.
+fn read_file(options: &Options, f: &Path) -> Result<Vec<u8>, FmtError> {
+    fs::read(f).map_err(|source| match options.listed_at.get(f) {
+        Some((list, line)) => FmtError::ReadListedFile {
+            path: f.to_path_buf(),
+            list: list.clone(),
+            line: *line,
+            source,
+        },
+        None => FmtError::ReadFile {
+            path: f.to_path_buf(),
+            source,
+        },
+    })
+}
 
-            io::stdout()
-                .write_all(fixed.as_bytes())
-                .map_err(|e| format!("failed to write stdout: {e}"))?;
-            return Ok(());
-        }
+/// Whether `f` is `-`, standing for stdin in the list of files.
+fn is_stdin(f: &Path) -> bool {
+    f.as_os_str() == "-"
+}
 
-        // Files provided: format each file via stdin and write to stdout
-        let mut out = io::stdout();
-        for f in files {
-            let input = fs::read_to_string(&f)
-                .map_err(|e| format!("failed to read {}: {e}", f.display()))?;
+/// The path a listed file is formatted as: its own, or the `--stdin-filename` for `-`.
+fn source_path<'a>(options: &'a Options, f: &'a Path) -> Option<&'a Path> {
+    if is_stdin(f) {
+        options.stdin_filename.as_deref()
+    } else {
+        Some(f)
+    }
+}
+
+/// Reads the source piped to stdin.
+fn read_stdin(options: &Options) -> Result<String, FmtError> {
+    if let Some(code) = &options.stdin_string {
+        return Ok(code.clone());
+    }
+    if io::stdin().is_terminal() {
+        let eof = if cfg!(windows) {
+            "Ctrl-Z, Enter"
+        } else {
+            "Ctrl-D"
+        };
+        log(
+            "<stdin>",
+            &format!("reading ChucK source from the terminal; end it with {eof}"),
+        );
+    }
+    let mut bytes = Vec::new();
+    io::stdin()
+        .read_to_end(&mut bytes)
+        .map_err(FmtError::Stdin)?;
+    decode_source(options, bytes, Path::new("<stdin>"))
+}
 
-            let fixed = process_string(&clang_format, &opts, &input)?;
+/// Decodes source bytes as UTF-8, naming the first invalid byte unless `--lossy` was given.
+fn decode_source(options: &Options, bytes: Vec<u8>, path: &Path) -> Result<String, FmtError> {
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        Err(e) if options.lossy => Ok(String::from_utf8_lossy(e.as_bytes()).into_owned()),
+        Err(e) => Err(FmtError::InvalidUtf8 {
+            path: path.to_path_buf(),
+            offset: e.utf8_error().valid_up_to(),
+        }),
+    }
+}
 
-            out.write_all(fixed.as_bytes())
-                .map_err(|e| format!("failed to write stdout: {e}"))?;
+/// Formats `input` read from `path` (`None` for stdin) with the `.chuckfmt.toml` that applies to
+/// it, forwarding clang-format's warnings to our stderr.
+fn format_source(
+    options: &Options,
+    opts: &[String],
+    path: Option<&Path>,
+    input: &str,
+) -> Result<String, FmtError> {
+    let clang_format = options.clang_format.as_deref();
+    let mut config = load_config(path)?;
+    for &(family, spacing) in &options.spacing {
+        config.spacing.set(family, spacing);
+    }
+    if options.transforms_only {
+        config.chain.reflow = false;
+    }
+    let mut opts = opts.to_vec();
+    let language = options.assume_language.unwrap_or(config.language);
+    if has_assume_filename(&opts) {
+        complete_assume_filename(&mut opts, language.extension());
+    } else {
+        opts.push(format!(
+            "--assume-filename={}",
+            assume_filename(path, language, config.assume_filename.as_deref())
+        ));
+    }
+    let name = path.map_or_else(|| "<stdin>".to_string(), |p| p.display().to_string());
+    if options.verbose {
+        match clang_format {
+            Some(clang) => log(&name, &format!("{} {}", clang.display(), opts.join(" "))),
+            None => log(&name, "clang-format skipped"),
         }
-        return Ok(());
     }
+    if let Some(clang) = clang_format
+        && options.print_clang_command
+    {
+        let command: Vec<String> = std::iter::once(clang.display().to_string())
+            .chain(opts.iter().cloned())
+            .map(|arg| shell_quote(&arg))
+            .collect();
+        log(
+            &name,
+            &format!("clang-format command: {}", command.join(" ")),
+        );
+    }
+    // `first_line` is the line of the source `code` starts on: 1, or a Markdown block's
+    let format_code = |code: &str, first_line: usize| {
+        let normalized;
+        let code = if options.normalize_unicode {
+            normalized = normalize_unicode_operators(code);
+            &normalized
+        } else {
+            code
+        };
+        let formatted = process_string(clang_format, &opts, options.timeout, &config, code)?;
+        eprint!("{}", formatted.stderr);
+        if options.fail_on_warnings && !formatted.stderr.trim().is_empty() {
+            return Err(FmtError::ClangFormatWarned { name: name.clone() });
+        }
+        if options.verbose {
+            let fired = if formatted.transforms.is_empty() {
+                "none".to_string()
+            } else {
+                formatted.transforms.join(", ")
+            };
+            log(&name, &format!("transforms: {fired}"));
+        }
+        if options.warn_suspicious {
+            for d in diagnostics::check(&formatted.output) {
+                log(
+                    &format!("{name}:{}", first_line - 1 + d.line),
+                    &format!("warning: {}", d.message),
+                );
+            }
+        }
+        Ok(formatted.output)
+    };
+    if options.markdown {
+        markdown::format_blocks(input, format_code)
+    } else {
+        format_code(input, 1)
+    }
+}
 
-    // In-place mode: require at least one file
-    if files.is_empty() {
-        return Err("chuckfmt: -i requires at least one file".to_string());
+/// Parses a `--spacing` value: comma-separated `FAMILY=spaced|tight` pairs.
+fn parse_spacing(value: &str) -> Result<Vec<(&'static str, OperatorSpacing)>, FmtError> {
+    value
+        .split(',')
+        .map(|pair| {
+            let (family, spacing) = pair
+                .split_once('=')
+                .and_then(|(family, spacing)| {
+                    let family = Spacing::FAMILIES.iter().find(|&&f| f == family.trim())?;
+                    Some((*family, OperatorSpacing::parse(spacing.trim())?))
+                })
+                .ok_or_else(|| {
+                    FmtError::Usage(format!(
+                        "invalid --spacing value: {pair} (expected FAMILY=spaced or FAMILY=tight, \
+                         with FAMILY one of {})",
+                        Spacing::FAMILIES.join(", ")
+                    ))
+                })?;
+            Ok((family, spacing))
+        })
+        .collect()
+}
+
+/// Whether `opts` has a clang-format option measured in bytes of the input (`-offset`,
+/// `-length` or `-cursor`, in any of their spellings).
+fn has_byte_offsets(opts: &[String]) -> bool {
+    opts.iter().any(|opt| {
+        let name = opt.split_once('=').map_or(opt.as_str(), |(name, _)| name);
+        matches!(name.trim_start_matches('-'), "offset" | "length" | "cursor")
+            && opt.starts_with('-')
+    })
+}
+
+/// Whether `opts` has clang-format's `-lines` option, in any of its spellings.
+fn has_lines(opts: &[String]) -> bool {
+    opts.iter().any(|opt| {
+        let name = opt.split_once('=').map_or(opt.as_str(), |(name, _)| name);
+        name == "-lines" || name == "--lines"
+    })
+}
+
+/// Quotes `arg` for a POSIX shell if it has characters the shell would interpret, so a printed
+/// command can be pasted back into a terminal.
+fn shell_quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-+=/.,:@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
     }
+}
 
-    // Remove -i from options for the stdin formatting path
-    let opts_no_i: Vec<String> = opts.into_iter().filter(|o| o != "-i").collect();
+/// Writes a `--verbose` line about `name` to stderr, so formatted stdout stays clean.
+fn log(name: &str, message: &str) {
+    eprintln!("{}: {name}: {message}", env!("CARGO_PKG_NAME"));
+}
 
-    for f in files {
-        let input =
-            fs::read_to_string(&f).map_err(|e| format!("failed to read {}: {e}", f.display()))?;
+/// The name clang-format should assume for a source file: its real path with the `language`'s
+/// extension appended, so `.clang-format` files are discovered next to the source while that
+/// language is still selected (for stdin: `code.java` or `code.cpp` in the current directory).
+/// A configured `name` takes the place of the file's own (or of `code`), in the same directory.
+fn assume_filename(path: Option<&Path>, language: Language, name: Option<&str>) -> String {
+    let extension = language.extension();
+    match (path, name) {
+        (Some(p), Some(name)) => format!("{}.{extension}", p.with_file_name(name).display()),
+        (Some(p), None) => format!("{}.{extension}", p.display()),
+        (None, name) => format!("{}.{extension}", name.unwrap_or("code")),
+    }
+}
 
-        let fixed = process_string(&clang_format, &opts_no_i, &input)?;
+/// Discovers the config for a source file from its directory upwards (for stdin: from the
+/// current directory).
+///
+/// Each config file is parsed (and its custom transforms compiled) once, not once per file it
+/// applies to; it is read again only if it was modified since, e.g. while `--lsp` runs.
+fn load_config(path: Option<&Path>) -> Result<Config, FmtError> {
+    static LOADED: Mutex<Vec<(PathBuf, Option<SystemTime>, Config)>> = Mutex::new(Vec::new());
+
+    let dir = match path.and_then(Path::parent) {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+    };
+    let Some(file) = Config::find(&dir) else {
+        return Ok(Config::default());
+    };
+    let modified = fs::metadata(&file).and_then(|m| m.modified()).ok();
+    let mut loaded = LOADED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((_, _, config)) = loaded
+        .iter()
+        .find(|(f, m, _)| *f == file && m.is_some() && *m == modified)
+    {
+        return Ok(config.clone());
+    }
+    let config = Config::load(&file)?;
+    loaded.retain(|(f, _, _)| *f != file);
+    loaded.push((file, modified, config.clone()));
+    Ok(config)
+}
 
-        // Match bash behavior: overwrite the file (no "only if changed" optimization)
-        fs::write(&f, fixed).map_err(|e| format!("failed to write {}: {e}", f.display()))?;
+/// Formats each file (or stdin, treated as the `--stdin-filename`, if there are none) without
+/// writing anything, reporting the ones whose formatted output differs from their contents: as a
+/// unified diff on stdout if `show_diff`, otherwise as a path on stderr.
+fn check_files(
+    options: &Options,
+    opts: &[String],
+    files: &[PathBuf],
+    show_diff: bool,
+) -> Result<(), FmtError> {
+    let format_one = |path: Option<&Path>, input: String| {
+        format_source(options, opts, path, &input).map(|fixed| (input, fixed))
+    };
+    let mut unformatted = 0usize;
+    let mut report = |path: Option<&Path>, input: &str, fixed: &str| -> Result<(), FmtError> {
+        if fixed == input {
+            return Ok(());
+        }
+        unformatted += 1;
+        let name = path.map_or_else(|| "<stdin>".to_string(), |p| p.display().to_string());
+        if show_diff {
+            let patch = diff::unified_diff(input, fixed, &name, &format!("{name} (formatted)"));
+            io::stdout()
+                .write_all(patch.as_bytes())
+                .map_err(FmtError::Stdout)?;
+        } else {
+            eprintln!("{name}");
+        }
+        Ok(())
+    };
+
+    if files.is_empty() {
+        let path = options.stdin_filename.as_deref();
+        let (input, fixed) = format_one(path, read_stdin(options)?)?;
+        report(path, &input, &fixed)?;
     }
 
+    // files are read and formatted in parallel, then reported in order
+    let results = par_map(files, options.jobs, |f| {
+        read_source(options, f).and_then(|input| format_one(source_path(options, f), input))
+    });
+    let mut errors = Vec::new();
+    for (f, result) in files.iter().zip(results) {
+        if let Err(e) =
+            result.and_then(|(input, fixed)| report(source_path(options, f), &input, &fixed))
+        {
+            errors.push(e);
+        }
+    }
+    report_failures(errors, files.len())?;
+
+    if unformatted > 0 {
+        return Err(FmtError::Unformatted(unformatted));
+    }
     Ok(())
 }
 
+// -------------------- Atomic writes --------------------
+
+/// Replaces `path` with `contents` by writing a temporary file next to it and renaming it over
+/// the original, so an interrupted run never leaves a truncated file behind. The original's
+/// permissions (and, on unix, ownership where allowed) are carried over. A symlink is followed,
+/// so the file it points to is replaced and the link stays a link.
+fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), FmtError> {
+    // a file that doesn't exist yet (a new `-o` target) has nothing to resolve
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = match target.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let name = target
+        .file_name()
+        .map_or_else(|| "chuckfmt".into(), |n| n.to_string_lossy().into_owned());
+    // Unique per process and per call, since files are written from several threads
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let tmp = dir.join(format!(
+        ".{name}.chuckfmt-{}-{}.tmp",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let write_err = |step: String| {
+        move |source| FmtError::WriteFile {
+            path: path.to_path_buf(),
+            step,
+            source,
+        }
+    };
+
+    let result = (|| {
+        fs::write(&tmp, contents).map_err(write_err(format!(
+            "creating temporary file {}",
+            tmp.display()
+        )))?;
+        if let Ok(meta) = fs::metadata(&target) {
+            fs::set_permissions(&tmp, meta.permissions()).map_err(write_err(format!(
+                "copying permissions to {}",
+                tmp.display()
+            )))?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                // Best effort: only root can give a file away to another user
+                let _ = std::os::unix::fs::chown(&tmp, Some(meta.uid()), Some(meta.gid()));
+            }
+        }
+        fs::rename(&tmp, &target).map_err(write_err(format!("renaming {} over it", tmp.display())))
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+// -------------------- Parallel processing --------------------
+
+/// Runs `f` over `items` on up to `jobs` threads, returning the results in input order.
+fn par_map<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..items.len()).map(|_| None).collect::<Vec<Option<R>>>());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(i) else { break };
+                    let r = f(item);
+                    results.lock().unwrap()[i] = Some(r);
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.expect("every item is processed"))
+        .collect()
+}
+
 // -------------------- Arg parsing (opts + files) --------------------
 
-fn has_assume_filename(opts: &[String]) -> bool {
-    opts.iter().any(|o| {
-        o == "--assume-filename"
-            || o == "-assume-filename"
-            || o.starts_with("--assume-filename=")
-            || o.starts_with("-assume-filename=")
-    })
+/// Removes chuckfmt's own `flag` from `args` (only before a `--` delimiter, so it is never
+/// forwarded to clang-format), returning whether it was present.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let end = args.iter().position(|a| a == "--").unwrap_or(args.len());
+    let before = args.len();
+    let mut i = 0usize;
+    args.retain(|a| {
+        i += 1;
+        i > end || a != flag
+    });
+    args.len() != before
+}
+
+/// Parses a `START:END` byte range with `START <= END`.
+fn parse_byte_range(range: &str) -> Option<(usize, usize)> {
+    let (start, end) = range.split_once(':')?;
+    let (start, end) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+    (start <= end).then_some((start, end))
+}
+
+/// Removes chuckfmt's own `name=value` / `name value` option from `args` (only before a `--`
+/// delimiter), returning its last value.
+fn take_value(args: &mut Vec<String>, name: &str) -> Result<Option<String>, FmtError> {
+    Ok(take_values(args, &[name])?.pop())
+}
+
+/// Removes every occurrence of options spelled as any of `names` (`name=value` or
+/// `name value`, only before a `--` delimiter) from `args`, returning their values in order.
+fn take_values(args: &mut Vec<String>, names: &[&str]) -> Result<Vec<String>, FmtError> {
+    let mut values = Vec::new();
+    let mut i = 0usize;
+    while i < args.len() && args[i] != "--" {
+        let inline = args[i]
+            .split_once('=')
+            .filter(|(n, _)| names.contains(n))
+            .map(|(_, v)| v.to_string());
+        if let Some(v) = inline {
+            values.push(v);
+            args.remove(i);
+        } else if names.contains(&args[i].as_str()) {
+            if i + 1 >= args.len() || args[i + 1] == "--" {
+                return Err(FmtError::Usage(format!("{} requires a value", args[i])));
+            }
+            values.push(args.remove(i + 1));
+            args.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    Ok(values)
+}
+
+/// Removes chuckfmt's own `name` / `name=value` option (only before a `--` delimiter), whose
+/// value is optional and never taken from the next argument. Returns `Some(None)` for the bare
+/// flag and `Some(Some(value))` for the last `name=value`.
+fn take_optional_value(args: &mut Vec<String>, name: &str) -> Option<Option<String>> {
+    let prefix = format!("{name}=");
+    let end = args.iter().position(|a| a == "--").unwrap_or(args.len());
+    let mut found = None;
+    let mut i = 0usize;
+    args.retain(|a| {
+        i += 1;
+        if i > end {
+            return true;
+        }
+        if a == name {
+            found = Some(None);
+        } else if let Some(v) = a.strip_prefix(&prefix) {
+            found = Some(Some(v.to_string()));
+        } else {
+            return true;
+        }
+        false
+    });
+    found
 }
 
-/// Mirrors your bash wrapper parsing:
-/// - If `--` exists: everything before is opts, everything after is files (ignoring "-" and "--")
-/// - Else heuristic:
+/// Mirrors your bash wrapper parsing, GNU style:
+/// - Up to the first `--` (or throughout, without one), a heuristic:
 ///   - options that take a separate value set skip_next and both tokens go into opts
-///   - tokens starting with '@', '-' (including "-") go into opts
+///   - a bare "-" (stdin) goes into files
+///   - other tokens starting with '@' or '-' go into opts
 ///   - everything else goes into files
-fn split_opts_files(args: &[String]) -> (Vec<String>, Vec<PathBuf>) {
-    if let Some(pos) = args.iter().position(|a| a == "--") {
-        let opts = args[..pos].to_vec();
-        let mut files = Vec::new();
-        for tok in &args[pos + 1..] {
-            if tok == "-" || tok == "--" {
-                continue;
-            }
-            files.push(PathBuf::from(tok));
+/// - Everything after the first `--` is a file (further `--`s are dropped), so files may come
+///   before and after options alike. A name there that starts with `-` and doesn't exist is an
+///   error: it is most likely an option given on the wrong side of `--`.
+fn split_opts_files(args: &[String]) -> Result<(Vec<String>, Vec<PathBuf>), FmtError> {
+    let (args, after) = match args.iter().position(|a| a == "--") {
+        Some(pos) => (&args[..pos], &args[pos + 1..]),
+        None => (args, &[][..]),
+    };
+    let (opts, mut files) = split_by_heuristic(args);
+    for tok in after {
+        if tok == "--" {
+            continue;
+        }
+        if tok.starts_with('-') && tok != "-" && !Path::new(tok).exists() {
+            return Err(FmtError::Usage(format!(
+                "{tok}: no such file; everything after -- is a file, so put options before it"
+            )));
         }
-        return (opts, files);
+        files.push(PathBuf::from(tok));
     }
+    Ok((opts, files))
+}
 
+/// The opts/files heuristic of [`split_opts_files`] for arguments without a `--`.
+fn split_by_heuristic(args: &[String]) -> (Vec<String>, Vec<PathBuf>) {
     let value_takers = [
         "-Wno-error",
         "--Wno-error",
@@ -346,7 +1429,7 @@ fn split_opts_files(args: &[String]) -> (Vec<String>, Vec<PathBuf>) {
             continue;
         }
 
-        if tok.starts_with('@') || tok == "-" || tok.starts_with('-') {
+        if tok != "-" && (tok.starts_with('@') || tok.starts_with('-')) {
             opts.push(tok.clone());
             continue;
         }
@@ -357,186 +1440,155 @@ fn split_opts_files(args: &[String]) -> (Vec<String>, Vec<PathBuf>) {
     (opts, files)
 }
 
-// -------------------- --files list expansion (no dedup) --------------------
-
-fn expand_files_from_list(opts: &[String], files: &mut Vec<PathBuf>) -> Result<(), String> {
-    // Expand --files <listfile> / --files=<listfile> and -files variants
-    if let Some(listfile) = find_option_value_in(opts, "--files", "-files") {
-        add_files_from_list(files, &listfile)?;
+// -------------------- Directory expansion --------------------
+
+/// Replaces every directory in `files` with the files below it (recursively, sorted) with a
+/// recognized extension (see [`recognized_extensions`]), skipping what `.gitignore`/`.ignore`
+/// files exclude if `respect_ignore`. Other paths are kept as given.
+fn expand_directories(
+    options: &Options,
+    files: &mut Vec<PathBuf>,
+    respect_ignore: bool,
+) -> Result<(), FmtError> {
+    let mut expanded = Vec::with_capacity(files.len());
+    for f in files.drain(..) {
+        if f.is_dir() {
+            let ignore = if respect_ignore {
+                Some(Ignore::for_root(&f)?)
+            } else {
+                None
+            };
+            let extensions = recognized_extensions(options, &f)?;
+            walk_dir(&f, &extensions, ignore.as_ref(), &mut expanded)?;
+        } else {
+            expanded.push(f);
+        }
     }
+    *files = expanded;
     Ok(())
 }
 
-fn find_option_value_in(opts: &[String], long: &str, short: &str) -> Option<String> {
-    // --opt=value / -opt=value
-    for a in opts {
-        if let Some(rest) = a.strip_prefix(&(long.to_string() + "=")) {
-            return Some(rest.to_string());
-        }
-        if let Some(rest) = a.strip_prefix(&(short.to_string() + "=")) {
-            return Some(rest.to_string());
+fn walk_dir(
+    dir: &Path,
+    extensions: &Extensions,
+    ignore: Option<&Ignore>,
+    out: &mut Vec<PathBuf>,
+) -> Result<(), FmtError> {
+    let read_err = |source| FmtError::ReadFile {
+        path: dir.to_path_buf(),
+        source,
+    };
+    let mut entries = fs::read_dir(dir)
+        .map_err(read_err)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(read_err)?;
+    entries.sort_by_key(|e| e.path());
+
+    for entry in entries {
+        let path = entry.path();
+        // file_type() doesn't follow symlinks, so symlinked directories can't loop
+        let is_dir = entry.file_type().map_err(read_err)?.is_dir();
+        if let Some(ignore) = ignore
+            && ((is_dir && entry.file_name() == ".git") || ignore.is_ignored(&path, is_dir))
+        {
+            continue;
         }
-    }
-    // --opt value / -opt value (within opts slice)
-    let mut i = 0usize;
-    while i < opts.len() {
-        let a = &opts[i];
-        if a == long || a == short {
-            if i + 1 < opts.len() {
-                return Some(opts[i + 1].clone());
-            } else {
-                return None;
-            }
+        if is_dir {
+            let sub = ignore.map(|i| i.enter(&path)).transpose()?;
+            walk_dir(&path, extensions, sub.as_ref(), out)?;
+        } else if extensions.matches(&path) {
+            out.push(path);
         }
-        i += 1;
     }
-    None
+    Ok(())
 }
 
-fn add_files_from_list(out: &mut Vec<PathBuf>, listfile: &str) -> Result<(), String> {
-    let content = fs::read_to_string(listfile)
-        .map_err(|e| format!("failed to read --files list '{}': {e}", listfile))?;
-    for line in content.lines() {
-        let t = line.trim();
-        if t.is_empty() {
-            continue;
-        }
-        out.push(PathBuf::from(t));
+/// The extensions recognized as ChucK for files in `dir`: those given with `--extension`, else
+/// those of the `.chuckfmt.toml` that applies there (`.ck` and `.chuck` by default). Directory
+/// walks, `--only-ck` and `--staged` all go through here.
+fn recognized_extensions(options: &Options, dir: &Path) -> Result<Extensions, FmtError> {
+    match &options.extensions {
+        Some(extensions) => Ok(extensions.clone()),
+        None => Ok(load_config(Some(&dir.join(CONFIG_FILE_NAME)))?.extensions),
     }
-    Ok(())
 }
 
-// -------------------- clang-format resolution --------------------
+// -------------------- --files list expansion (no dedup) --------------------
 
-/// Locates the clang-format binary to use.
+/// Expands every `--files <listfile>` / `--files=<listfile>` (and `-files` variants) in order,
+/// taking the options out of `opts` so they aren't forwarded to clang-format, and noting in
+/// `listed_at` where each file was listed. A listfile of `-` is read from stdin; returns whether
+/// one was.
 ///
-/// Resolution order:
-/// 1. `CLANG_FORMAT_BIN` environment variable (must be executable)
-/// 2. `clang-format` in PATH
-fn resolve_clang_format() -> Result<PathBuf, String> {
-    if let Ok(p) = env::var("CLANG_FORMAT_BIN") {
-        let pb = PathBuf::from(p);
-        if is_executable(&pb) {
-            return Ok(pb);
-        }
-        return Err(format!(
-            "CLANG_FORMAT_BIN is set but not executable: {}",
-            pb.display()
+/// Every line of a list is a source path, never another list: there is no nested-include syntax,
+/// so a line naming a list file is formatted like any other file. The one exception is a line
+/// naming one of this run's own `--files` lists, which is rejected rather than formatted (or, with
+/// `-i`, overwritten) as ChucK.
+fn expand_files_from_list(
+    opts: &mut Vec<String>,
+    files: &mut Vec<PathBuf>,
+    listed_at: &mut BTreeMap<PathBuf, (PathBuf, usize)>,
+) -> Result<bool, FmtError> {
+    let lists = take_values(opts, &["--files", "-files"])?;
+    let from_stdin = lists.iter().filter(|l| *l == "-").count();
+    if from_stdin > 1 {
+        return Err(FmtError::Usage(
+            "only one --files list can be read from stdin (-)".to_string(),
         ));
     }
-
-    if let Some(pb) = find_in_path(exe_name("clang-format")) {
-        return Ok(pb);
-    }
-
-    Err(format!(
-        "clang-format not found.\n\
-         - Install clang-format and ensure it's on PATH, or\n\
-         - Set CLANG_FORMAT_BIN to the full path of clang-format.\n\
-         Example: CLANG_FORMAT_BIN=/usr/bin/clang-format {} ...",
-        env!("CARGO_PKG_NAME")
-    ))
-}
-
-fn exe_name(base: &str) -> String {
-    if cfg!(windows) {
-        format!("{base}.exe")
-    } else {
-        base.to_string()
+    let list_paths: HashSet<PathBuf> = lists
+        .iter()
+        .filter(|l| *l != "-")
+        .filter_map(|l| fs::canonicalize(l).ok())
+        .collect();
+    for listfile in &lists {
+        add_files_from_list(files, listed_at, listfile, &list_paths)?;
     }
+    Ok(from_stdin == 1)
 }
 
-fn is_executable(p: &Path) -> bool {
-    if !p.is_file() {
-        return false;
-    }
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        fs::metadata(p)
-            .map(|m| (m.permissions().mode() & 0o111) != 0)
-            .unwrap_or(false)
-    }
-    #[cfg(windows)]
-    {
-        true
-    }
+/// Drops files that name the same file as an earlier one (after canonicalization), keeping the
+/// first spelling. Paths that can't be canonicalized (e.g. missing files) are compared as given,
+/// so their errors are still reported when they are read.
+fn dedup_files(files: &mut Vec<PathBuf>) {
+    let mut seen = HashSet::new();
+    files.retain(|f| seen.insert(fs::canonicalize(f).unwrap_or_else(|_| f.clone())));
 }
 
-fn find_in_path(program: String) -> Option<PathBuf> {
-    if program.contains(std::path::MAIN_SEPARATOR) {
-        let p = PathBuf::from(program);
-        return if is_executable(&p) { Some(p) } else { None };
-    }
-
-    let path_var: OsString = env::var_os("PATH")?;
-    for dir in env::split_paths(&path_var) {
-        let candidate = dir.join(&program);
-        if is_executable(&candidate) {
-            return Some(candidate);
+fn add_files_from_list(
+    out: &mut Vec<PathBuf>,
+    listed_at: &mut BTreeMap<PathBuf, (PathBuf, usize)>,
+    listfile: &str,
+    list_paths: &HashSet<PathBuf>,
+) -> Result<(), FmtError> {
+    let content = if listfile == "-" {
+        let mut content = String::new();
+        io::stdin()
+            .read_to_string(&mut content)
+            .map_err(FmtError::Stdin)?;
+        content
+    } else {
+        fs::read_to_string(listfile).map_err(|source| FmtError::ReadFileList {
+            path: PathBuf::from(listfile),
+            source,
+        })?
+    };
+    let list = PathBuf::from(if listfile == "-" { "<stdin>" } else { listfile });
+    for (idx, line) in content.lines().enumerate() {
+        let t = line.trim();
+        if t.is_empty() {
+            continue;
         }
-        if cfg!(windows) && !program.to_lowercase().ends_with(".exe") {
-            let candidate_exe = dir.join(format!("{program}.exe"));
-            if is_executable(&candidate_exe) {
-                return Some(candidate_exe);
-            }
+        if fs::canonicalize(t).is_ok_and(|p| list_paths.contains(&p)) {
+            return Err(FmtError::Usage(format!(
+                "{}:{}: {t} is a --files list itself; list entries are source files and are \
+                 never expanded as lists",
+                list.display(),
+                idx + 1
+            )));
         }
+        listed_at.insert(PathBuf::from(t), (list.clone(), idx + 1));
+        out.push(PathBuf::from(t));
     }
-    None
-}
-
-// -------------------- Running clang-format (stdin -> stdout capture) --------------------
-
-fn process_string(clang_format: &Path, opts: &[String], input: &str) -> Result<String, String> {
-    let pre_formatted = apply_pre_formatting_transforms(input);
-    let formatted = run_clang_format_on_stdin_capture(clang_format, opts, &pre_formatted)?;
-    Ok(apply_transforms_preserving_comments(&formatted))
-}
-
-/// Runs clang-format by sending `input` to stdin, capturing stdout as a String.
-///
-/// stderr is inherited (like your bash wrapper; useful for warnings).
-fn run_clang_format_on_stdin_capture(
-    clang: &Path,
-    opts: &[String],
-    input: &str,
-) -> Result<String, String> {
-    let mut child = Command::new(clang)
-        .args(opts)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::inherit())
-        .spawn()
-        .map_err(|e| format!("failed to launch clang-format: {e}"))?;
-
-    {
-        let mut stdin = child
-            .stdin
-            .take()
-            .ok_or_else(|| "failed to open clang-format stdin".to_string())?;
-        stdin
-            .write_all(input.as_bytes())
-            .map_err(|e| format!("failed writing clang-format stdin: {e}"))?;
-    }
-
-    let mut out = String::new();
-    child
-        .stdout
-        .take()
-        .ok_or_else(|| "failed to capture clang-format stdout".to_string())?
-        .read_to_string(&mut out)
-        .map_err(|e| format!("failed reading clang-format stdout: {e}"))?;
-
-    let status = child
-        .wait()
-        .map_err(|e| format!("failed waiting for clang-format: {e}"))?;
-
-    if !status.success() {
-        return Err(format!(
-            "clang-format failed with exit code {:?}",
-            status.code()
-        ));
-    }
-
-    Ok(out)
+    Ok(())
 }
@@ -0,0 +1,105 @@
+//! ChucK code blocks in Markdown documents.
+//!
+//! A fenced block (```` ``` ```` or `~~~`, at any indentation) whose info string starts with one
+//! of [`LANGUAGES`] is ChucK. Everything else is left byte for byte: prose, other fenced blocks,
+//! a fence nested inside another (longer) one, which is only that block's content, and a block
+//! whose fence is never closed.
+
+use crate::{FmtError, format_chuck};
+
+/// First words of an info string (compared ignoring case) that mark a fenced block as ChucK.
+pub const LANGUAGES: &[&str] = &["chuck", "ck"];
+
+/// Formats every ChucK block of `doc` with [`format_chuck`], passing `opts` to clang-format.
+pub fn format(doc: &str, opts: &[String]) -> Result<String, FmtError> {
+    format_blocks(doc, |code, _| format_chuck(code, opts))
+}
+
+/// Runs `format` over the code of every ChucK block in `doc`, returning the document with the
+/// results in their place. `format` gets the code with the indentation of its fence taken off
+/// (it is put back on every non-blank line of the result) and the 1-based line of `doc` the
+/// code starts on; the fences themselves, info strings included, are kept as written.
+pub fn format_blocks<E>(
+    doc: &str,
+    mut format: impl FnMut(&str, usize) -> Result<String, E>,
+) -> Result<String, E> {
+    let lines: Vec<&str> = doc.split_inclusive('\n').collect();
+    let mut out = String::with_capacity(doc.len());
+    let mut i = 0;
+    while i < lines.len() {
+        let Some(fence) = Fence::open(lines[i]) else {
+            out.push_str(lines[i]);
+            i += 1;
+            continue;
+        };
+        let Some(close) = (i + 1..lines.len()).find(|&j| fence.is_closed_by(lines[j])) else {
+            // an unclosed block runs to the end of the document; leave it as written
+            lines[i..].iter().for_each(|line| out.push_str(line));
+            break;
+        };
+        let body = &lines[i + 1..close];
+        out.push_str(lines[i]);
+        if fence.is_chuck() {
+            let code: String = body
+                .iter()
+                .map(|line| {
+                    line.strip_prefix(fence.indent)
+                        .unwrap_or_else(|| line.trim_start_matches([' ', '\t']))
+                })
+                .collect();
+            for line in format(&code, i + 2)?.split_inclusive('\n') {
+                if !line.trim().is_empty() {
+                    out.push_str(fence.indent);
+                }
+                out.push_str(line);
+            }
+        } else {
+            body.iter().for_each(|line| out.push_str(line));
+        }
+        out.push_str(lines[close]);
+        i = close + 1;
+    }
+    Ok(out)
+}
+
+/// The opening line of a fenced block.
+struct Fence<'a> {
+    indent: &'a str,
+    marker: char,
+    len: usize,
+    info: &'a str,
+}
+
+impl<'a> Fence<'a> {
+    /// `line` as an opening fence: three or more backticks or tildes, then the info string.
+    fn open(line: &'a str) -> Option<Fence<'a>> {
+        let trimmed = line.trim_start_matches([' ', '\t']);
+        let indent = &line[..line.len() - trimmed.len()];
+        let marker = trimmed.chars().next().filter(|&c| c == '`' || c == '~')?;
+        let len = trimmed.len() - trimmed.trim_start_matches(marker).len();
+        let info = trimmed[len..].trim();
+        // a backtick fence can't have backticks in its info string (that's inline code)
+        if len < 3 || (marker == '`' && info.contains('`')) {
+            return None;
+        }
+        Some(Fence {
+            indent,
+            marker,
+            len,
+            info,
+        })
+    }
+
+    /// Whether `line` closes this block: at least as many of the same marker and nothing else.
+    fn is_closed_by(&self, line: &str) -> bool {
+        let fence = line.trim();
+        fence.len() >= self.len && fence.chars().all(|c| c == self.marker)
+    }
+
+    fn is_chuck(&self) -> bool {
+        self.info
+            .split(|c: char| c.is_whitespace() || c == ',' || c == '{')
+            .next()
+            .is_some_and(|word| LANGUAGES.iter().any(|l| word.eq_ignore_ascii_case(l)))
+    }
+}
@@ -0,0 +1,551 @@
+//! The ChucK transforms applied to clang-format's output, one function per operator family.
+//!
+//! Every function only looks at code: string literals and comments are expected to be masked
+//! beforehand, which [`crate::apply_transforms`] takes care of. Use [`apply_all`] (or [`ALL`]) to
+//! run them in the usual order.
+
+use crate::{ChainStyle, Config, OperatorSpacing, Spacing};
+use lazy_regex::{regex, regex_replace_all};
+
+/// A transform over masked code.
+pub type Transform = fn(&str) -> String;
+
+/// Every transform with its name in the `[transforms]` table of `.chuckfmt.toml`, in the order
+/// [`apply_all`] runs them.
+pub const ALL: &[(&str, Transform)] = &[
+    ("chuck_arrow", chuck_arrow),
+    ("unchuck", unchuck),
+    ("output", output),
+    ("at_chuck", at_chuck),
+    ("compound_chuck", compound_chuck),
+    ("upchuck", upchuck),
+    ("duration_colon", duration),
+    ("ternary", ternary),
+    ("print_brackets", print_brackets),
+    ("print_commas", print_commas),
+    ("polar", polar),
+    ("vector", vector),
+    ("complex", complex),
+    ("gruck", gruck),
+    ("ungruck", ungruck),
+    ("leading_sign", leading_sign),
+    ("sporks", sporks),
+    ("multiplication", multiplication),
+    ("cast", cast),
+    ("reference", reference),
+    ("array_brackets", array_brackets),
+];
+
+/// Runs every transform `config` leaves enabled over masked code, returning the result and the
+/// names of the transforms that changed it. Each transform sees one block of lines between blank
+/// lines at a time, so blank lines are kept exactly as clang-format left them.
+pub fn apply_all(code: &str, config: &Config) -> (String, Vec<String>) {
+    // undo the `-->` protection from apply_pre_formatting_transforms, and take the underscore
+    // it added off names made of `instanceof`
+    let mut s = regex_replace_all!(r"\binstanceof(_*)\b", code, |_, escaped: &str| {
+        match escaped.strip_prefix('_') {
+            Some(name) => format!("instanceof{name}"),
+            None => "-->".to_string(),
+        }
+    })
+    .into_owned();
+    let mut fired = Vec::new();
+
+    for &(name, transform) in ALL {
+        if !config.transform_enabled(name) {
+            continue;
+        }
+        let out = between_blank_lines(&s, transform);
+        if out != s {
+            fired.push(name.to_string());
+            s = out;
+        }
+    }
+
+    if config.chain.reflow {
+        let out = reflow_chains(&s, &config.chain);
+        if out != s {
+            fired.push("chain_reflow".to_string());
+            s = out;
+        }
+    }
+
+    let out = apply_spacing(&s, &config.spacing);
+    if out != s {
+        fired.push("spacing".to_string());
+        s = out;
+    }
+
+    // remove the ";" we added after @import statements: right after the (masked) path, before
+    // any trailing comment
+    let s = regex_replace_all!(
+        r"(?m)^([ \t]*@import[ \t]*\x{E000}[0-9]+\x{E001});",
+        &s,
+        "$1"
+    );
+    (s.into_owned(), fired)
+}
+
+/// Applies `transform` to each run of non-blank lines in `s` on its own, leaving the blank
+/// (or whitespace-only) lines between them untouched.
+fn between_blank_lines(s: &str, transform: Transform) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut start = 0;
+    for blank in regex!(r"\n(?:[ \t]*\n)+").find_iter(s) {
+        out.push_str(&transform(&s[start..blank.start()]));
+        out.push_str(blank.as_str());
+        start = blank.end();
+    }
+    out.push_str(&transform(&s[start..]));
+    out
+}
+
+/// `= >` -> `=>`
+pub fn chuck_arrow(s: &str) -> String {
+    regex_replace_all!(r"=\s*>", s, "=>").into_owned()
+}
+
+/// `chout<=x<=IO.nl();` -> `chout <= x <= IO.nl();`: a single space around the `<=` output
+/// operator (as clang-format spaces a `<=` comparison), keeping line breaks clang-format put next
+/// to it. It is told apart from the unchuck `=<` by its order, and `<<=` is left alone.
+pub fn output(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut last = 0;
+    for caps in regex!(r"([ \t]*)<=[ \t]*").captures_iter(s) {
+        let m = caps.get(0).unwrap();
+        let before = s[..m.start()].chars().next_back();
+        let after = s[m.end()..].chars().next();
+        if matches!(before, Some('<' | '=' | '!' | '>')) || matches!(after, Some('=' | '<' | '>')) {
+            continue;
+        }
+        out.push_str(&s[last..m.start()]);
+        // at the start or end of a line, the indentation or line break stays as it is
+        if before.is_none_or(|c| c == '\n') {
+            out.push_str(&caps[1]);
+        } else {
+            out.push(' ');
+        }
+        out.push_str("<=");
+        if after.is_some_and(|c| c != '\n') {
+            out.push(' ');
+        }
+        last = m.end();
+    }
+    out.push_str(&s[last..]);
+    out
+}
+
+/// `s = < dac` -> `s =< dac`, also in chains (`a =< b =< c`) and when clang-format broke the
+/// line between `=` and `<`. ChucK has no plain `=`, so a lone `=` before `<` can only be a split
+/// unchuck and `x = <y` becomes `x =< y`; an `=` ending another operator (`==`, `<=`, `!=`, ...)
+/// is left alone.
+pub fn unchuck(s: &str) -> String {
+    regex_replace_all!(
+        r"(?m)(^|[^=!<>+\-*/%&|^])([ \t]*)=\s*<[ \t]*(\S?)",
+        s,
+        |_, before: &str, space: &str, next: &str| {
+            // keep the indentation at the start of a line, and nothing after a trailing `=<`
+            let before = if before.is_empty() || before.chars().all(char::is_whitespace) {
+                format!("{before}{space}")
+            } else {
+                format!("{before} ")
+            };
+            if next.is_empty() {
+                format!("{before}=<")
+            } else {
+                format!("{before}=< {next}")
+            }
+        }
+    )
+    .into_owned()
+}
+
+/// `@ =>` -> `@=>`
+pub fn at_chuck(s: &str) -> String {
+    regex_replace_all!(r"@\s*=>", s, "@=>").into_owned()
+}
+
+/// `1 += > x` -> `1 +=> x` (also `-=>`, `*=>`, `/=>`, `%=>`, `&=>`, `|=>`, `^=>`, `>>=>`,
+/// `<<=>`). A real `>>=` / `<<=` is left alone since it has no `>` to merge with.
+pub fn compound_chuck(s: &str) -> String {
+    let s = regex_replace_all!(
+        r"([A-Za-z0-9_\)\]\x{E001}])[ \t]*([+\-*/%&|^]|<<|>>)[ \t]*=>",
+        s,
+        "$1 $2=>"
+    );
+    regex_replace_all!(r"((?:[+\-*/%&|^]|<<|>>)=>)[ \t]*(\S)", &s, "$1 $2").into_owned()
+}
+
+/// `adc= ^fft` -> `adc =^ fft`. ChucK has no plain `=`, so a lone `=` before `^` can only be a
+/// split upchuck; an `=` ending another operator (`==`, `!=`, `<=`, `>=`, `^=`, ...) is left
+/// alone.
+pub fn upchuck(s: &str) -> String {
+    regex_replace_all!(
+        r"(?m)(^|[^=!<>+\-*/%&|^])([ \t]*)=\s*\^\s*",
+        s,
+        |_, before: &str, space: &str| {
+            if before.is_empty() || before.chars().all(char::is_whitespace) {
+                // at the start of a line, keep the indentation
+                format!("{before}{space}=^ ")
+            } else {
+                format!("{before} =^ ")
+            }
+        }
+    )
+    .into_owned()
+}
+
+/// `1 ::second` -> `1::second`, also `x :: second` and `(n + 1) :: samp`. A left operand starting
+/// with a digit is a number, so `1e3 :: samp` and `0x10 :: ms` are covered too. C++-style
+/// `Namespace :: member` is not a duration and keeps its spacing: a capitalized name on the left
+/// is taken for a type, and a name on the right followed by a call or member access for a member.
+pub fn duration(s: &str) -> String {
+    regex_replace_all!(
+        r"([A-Za-z0-9_\.]+|[\)\]\x{E001}])([ \t]*::[ \t]*)([A-Za-z_][A-Za-z0-9_]*[ \t]*[\(\.]?|\()",
+        s,
+        |all: &str, left: &str, _: &str, right: &str| {
+            let name = left.rsplit('.').next().unwrap_or_default();
+            let number = left.starts_with(|c: char| c.is_ascii_digit() || c == '.');
+            let member = !number
+                && (name.starts_with(|c: char| c.is_ascii_uppercase())
+                    || right.len() > 1 && right.ends_with(['(', '.']));
+            if member {
+                all.to_string()
+            } else {
+                format!("{left}::{right}")
+            }
+        }
+    )
+    .into_owned()
+}
+
+/// `c?a:b` -> `c ? a : b`: a single space around `?` and the `:` answering it, keeping line
+/// breaks clang-format put next to them. Runs of colons (`1::second`) are never a ternary's, and
+/// a `:` with no open `?` in the same statement (`for (x : arr)`) is left alone.
+pub fn ternary(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut open = 0usize;
+    let mut last = 0;
+    for caps in regex!(r"([ \t]*)(\?|:+|[;{}])([ \t]*)").captures_iter(s) {
+        let (all, token) = (caps.get(0).unwrap(), caps.get(2).unwrap());
+        match token.as_str() {
+            "?" => open += 1,
+            ":" if open > 0 => open -= 1,
+            ";" | "{" | "}" => {
+                open = 0;
+                continue;
+            }
+            _ => continue,
+        }
+        out.push_str(&s[last..all.start()]);
+        // at the start or end of a line, the indentation or line break stays as it is
+        if all.start() == 0 || s[..all.start()].ends_with('\n') {
+            out.push_str(&caps[1]);
+        } else {
+            out.push(' ');
+        }
+        out.push_str(token.as_str());
+        if !(all.end() == s.len() || s[all.end()..].starts_with('\n')) {
+            out.push(' ');
+        }
+        last = all.end();
+    }
+    out.push_str(&s[last..]);
+    out
+}
+
+/// `<<<x>>>;` -> `<<< x >>>;`, however clang-format split the brackets (`< < <`, `<< <`, ...)
+pub fn print_brackets(s: &str) -> String {
+    let s = regex_replace_all!(r"<[ \t]*<[ \t]*<\s*", s, "<<< ");
+    regex_replace_all!(r"\s*>[ \t]*>[ \t]*>\s*;", &s, " >>>;").into_owned()
+}
+
+/// `<<< a ,b , c >>>` -> `<<< a, b, c >>>`
+pub fn print_commas(s: &str) -> String {
+    regex_replace_all!(r"(?s)<<<(.*?)>>>", s, |_, inner: &str| {
+        format!("<<<{}>>>", normalize_top_level_commas(inner))
+    })
+    .into_owned()
+}
+
+/// `% ( 1,.5 )` -> `%(1, .5)`, for polar literals only (not the modulo operator)
+pub fn polar(s: &str) -> String {
+    let s = regex_replace_all!(r"(?m)(^|[=\(,;\[\{:+\-*/&|^!<>])(\s*)%\s+\(", s, "$1$2%(");
+    let opens = regex!(r"(?m)(?:^|[=\(,;\[\{:+\-*/&|^!<>])\s*%\(")
+        .find_iter(&s)
+        .map(|m| m.end() - 1)
+        .collect();
+    tidy_literal_bodies(&s, opens)
+}
+
+/// `@ ( 1,2 ,3 )` -> `@(1, 2, 3)`
+pub fn vector(s: &str) -> String {
+    let s = regex_replace_all!(r"@\s*\(", s, "@(");
+    let opens = regex!(r"@\(").find_iter(&s).map(|m| m.end() - 1).collect();
+    tidy_literal_bodies(&s, opens)
+}
+
+/// `# ( 1,2 )` -> `#(1, 2)`
+pub fn complex(s: &str) -> String {
+    let s = regex_replace_all!(r"#\s*\(", s, "#(");
+    let opens = regex!(r"#\(").find_iter(&s).map(|m| m.end() - 1).collect();
+    tidy_literal_bodies(&s, opens)
+}
+
+/// `a-->b` -> `a --> b`; `i-- > 3` (decrement, then compare) is left alone
+pub fn gruck(s: &str) -> String {
+    let s = regex_replace_all!(r"(\S)[ \t]*-->", s, "$1 -->");
+    regex_replace_all!(r"-->[ \t]*(\S)", &s, "--> $1").into_owned()
+}
+
+/// `-- <` -> ` --< `
+pub fn ungruck(s: &str) -> String {
+    regex_replace_all!(r"\s*-\s*-\s*<\s*", s, " --< ").into_owned()
+}
+
+/// `- 3.14` -> `-3.14` wherever the sign is unary: at the start of a line, or after `(`, `[`,
+/// `,`, `return` or an operator (`x => - 1.0` -> `x => -1.0`). After an operand (`a - 1.0`) or
+/// `++`/`--` (`i-- - 1`) it is a subtraction and keeps its spaces.
+pub fn leading_sign(s: &str) -> String {
+    regex_replace_all!(
+        r"(?m)(^|\+\+|--|[(\[{,;:?=<>!&|^*/%+\-]|\breturn)([ \t]*[+-])[ \t]+([A-Za-z_\(\[]|[0-9]+(?:\.[0-9]*)?|\.[0-9]+)",
+        s,
+        |all: &str, before: &str, sign: &str, operand: &str| {
+            if before == "++" || before == "--" {
+                all.to_string()
+            } else {
+                format!("{before}{sign}{operand}")
+            }
+        }
+    )
+    .into_owned()
+}
+
+/// `spork ~foo` -> `spork ~ foo`, also when clang-format broke the line after `spork` or `~`
+pub fn sporks(s: &str) -> String {
+    regex_replace_all!(r"\bspork\s*~\s*", s, "spork ~ ").into_owned()
+}
+
+/// `2 *b` -> `2 * b`
+pub fn multiplication(s: &str) -> String {
+    regex_replace_all!(
+        r"([A-Za-z0-9_\)\]])\s*(\*)([A-Za-z0-9_\(\[])",
+        s,
+        "$1 $2 $3"
+    )
+    .into_owned()
+}
+
+/// `3.0$int` / `obj $Foo` -> `3.0 $ int` / `obj $ Foo`
+pub fn cast(s: &str) -> String {
+    regex_replace_all!(
+        r"([A-Za-z0-9_\.\)\]\x{E001}])[ \t]*\$[ \t]*([A-Za-z_])",
+        s,
+        "$1 $$ $2"
+    )
+    .into_owned()
+}
+
+/// `SinOsc@s;` / `Foo[] @bar` -> `SinOsc @ s;` / `Foo[] @ bar`. `@=>`, `@import` and `@(...)`
+/// vectors have no identifier on both sides, so they are left alone, and so are the `@` of
+/// `fun @construct()`, `fun void @destruct()` and `fun @operator +(...)`, which is part of the
+/// name.
+pub fn reference(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut last = 0;
+    for caps in regex!(r"([A-Za-z0-9_\]])[ \t]*@[ \t]*([A-Za-z_][A-Za-z0-9_]*)").captures_iter(s) {
+        let m = caps.get(0).unwrap();
+        // the statement so far: a `fun` with no `(` yet means `@` starts the function's name
+        let statement = s[..m.start()]
+            .rsplit([';', '{', '}'])
+            .next()
+            .unwrap_or_default();
+        if matches!(&caps[2], "construct" | "destruct" | "operator")
+            || regex!(r"\bfun(?:ction)?\b[^(]*$").is_match(statement)
+        {
+            continue;
+        }
+        out.push_str(&s[last..m.start()]);
+        out.push_str(&format!("{} @ {}", &caps[1], &caps[2]));
+        last = m.end();
+    }
+    out.push_str(&s[last..]);
+    out
+}
+
+/// `float data [ 10 ];` / `arr [i] [j]` -> `float data[10];` / `arr[i][j]`: subscripts and
+/// array declarations (`int arr [] @=> x`) keep `[` against the name and no spaces inside the
+/// brackets. An array literal after `return`, `else` or `do` keeps its space.
+pub fn array_brackets(s: &str) -> String {
+    let s = regex_replace_all!(
+        r"(\b[A-Za-z_][A-Za-z0-9_]*|\])[ \t]+\[",
+        s,
+        |all: &str, before: &str| match before {
+            "return" | "else" | "do" => all.to_string(),
+            _ => format!("{before}["),
+        }
+    );
+    let s = regex_replace_all!(r"\[[ \t]+(\S)", &s, "[$1");
+    regex_replace_all!(r"(\S)[ \t]+\]", &s, "$1]").into_owned()
+}
+
+/// Drops the spaces around the operator families `spacing` makes tight (`a => b` -> `a=>b`,
+/// `<<< x >>>` -> `<<<x>>>`, `spork ~ foo` -> `spork ~foo`). Spaced families are left as the
+/// other transforms and clang-format spaced them, and line breaks next to an operator stay.
+pub fn apply_spacing(s: &str, spacing: &Spacing) -> String {
+    let tight = |family: OperatorSpacing| family == OperatorSpacing::Tight;
+    let mut s = s.to_string();
+    if tight(spacing.chuck) {
+        s = regex_replace_all!(r"(\S)[ \t]+((?:@|<<|>>|[+\-*/%&|^])?=>|=<)", &s, "$1$2")
+            .into_owned();
+        s = regex_replace_all!(r"((?:@|<<|>>|[+\-*/%&|^])?=>|=<)[ \t]+(\S)", &s, "$1$2")
+            .into_owned();
+    }
+    if tight(spacing.upchuck) {
+        s = regex_replace_all!(r"(\S)[ \t]+=\^", &s, "$1=^").into_owned();
+        s = regex_replace_all!(r"=\^[ \t]+(\S)", &s, "=^$1").into_owned();
+    }
+    if tight(spacing.print) {
+        s = regex_replace_all!(r"<<<[ \t]+(\S)", &s, "<<<$1").into_owned();
+        s = regex_replace_all!(r"(\S)[ \t]+>>>", &s, "$1>>>").into_owned();
+    }
+    if tight(spacing.spork) {
+        s = regex_replace_all!(r"\bspork ~[ \t]+(\S)", &s, "spork ~$1").into_owned();
+    }
+    s
+}
+
+/// Lays out `=>` chains clang-format wrapped over several lines (`a => b =>` / `c => dac;`)
+/// the same way every time: each continuation line is indented `style.indent` spaces past the
+/// chain's first line, and the chuck operators at the breaks (`=>`, `@=>`, `+=>`, ...) end the
+/// line before or, with `style.break_before`, start the continuation line.
+pub fn reflow_chains(s: &str, style: &ChainStyle) -> String {
+    let trailing = regex!(r"[ \t]*((?:@|<<|>>|[+\-*/%&|^])?=>)[ \t]*$");
+    let leading = regex!(r"^[ \t]*((?:@|<<|>>|[+\-*/%&|^])?=>)[ \t]*");
+    let lines: Vec<&str> = s.split('\n').collect();
+    let breaks_after = |i: usize| {
+        i + 1 < lines.len()
+            && !lines[i + 1].trim().is_empty()
+            && (trailing.is_match(lines[i]) || leading.is_match(lines[i + 1]))
+    };
+
+    let mut out: Vec<String> = Vec::with_capacity(lines.len());
+    let mut i = 0;
+    while i < lines.len() {
+        let mut end = i;
+        while breaks_after(end) {
+            end += 1;
+        }
+        if end == i {
+            out.push(lines[i].to_string());
+            i += 1;
+            continue;
+        }
+
+        let first = lines[i];
+        let indent = &first[..first.len() - first.trim_start().len()];
+        let continuation = format!("{indent}{}", " ".repeat(style.indent));
+        // the code between the breaks, and the operators at the breaks
+        let mut pieces: Vec<&str> = Vec::new();
+        let mut ops: Vec<&str> = Vec::new();
+        for (k, line) in lines[i..=end].iter().enumerate() {
+            let mut text = line.trim();
+            if ops.len() < k
+                && let Some(caps) = leading.captures(text)
+            {
+                ops.push(caps.get(1).unwrap().as_str());
+                text = &text[caps.get(0).unwrap().end()..];
+            }
+            if k < end - i
+                && let Some(caps) = trailing.captures(text)
+            {
+                ops.push(caps.get(1).unwrap().as_str());
+                text = &text[..caps.get(0).unwrap().start()];
+            }
+            pieces.push(text);
+        }
+        if ops.len() + 1 != pieces.len() || pieces.iter().any(|p| p.is_empty()) {
+            // not a chain we understand; leave it as it is
+            out.extend(lines[i..=end].iter().map(|l| l.to_string()));
+            i = end + 1;
+            continue;
+        }
+
+        for (k, piece) in pieces.iter().enumerate() {
+            let mut line = if k == 0 {
+                indent.to_string()
+            } else {
+                continuation.clone()
+            };
+            if style.break_before && k > 0 {
+                line.push_str(ops[k - 1]);
+                line.push(' ');
+            }
+            line.push_str(piece);
+            if !style.break_before && k + 1 < pieces.len() {
+                line.push(' ');
+                line.push_str(ops[k]);
+            }
+            out.push(line);
+        }
+        i = end + 1;
+    }
+    out.join("\n")
+}
+
+/// Trims the spaces just inside each literal's parentheses (`opens` are the byte offsets of the
+/// `(`s) and normalizes its top-level commas. Literals are handled right to left so nested ones
+/// are tidied before the literal containing them.
+fn tidy_literal_bodies(s: &str, opens: Vec<usize>) -> String {
+    let mut s = s.to_string();
+    for &open in opens.iter().rev() {
+        let mut depth = 0usize;
+        let close = s[open..].char_indices().find_map(|(i, c)| {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(open + i);
+                    }
+                }
+                _ => {}
+            }
+            None
+        });
+        let Some(close) = close else {
+            continue;
+        };
+        let body = normalize_top_level_commas(s[open + 1..close].trim_matches([' ', '\t']));
+        s.replace_range(open + 1..close, &body);
+    }
+    s
+}
+
+/// Gives every comma outside brackets a single trailing space and no leading space, leaving the
+/// arguments of nested calls and array literals as they are. Commas at a line end keep their
+/// line break.
+fn normalize_top_level_commas(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut depth = 0usize;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                let trimmed = out.trim_end_matches([' ', '\t']).len();
+                out.truncate(trimmed);
+                out.push(',');
+                while chars.next_if(|&n| n == ' ' || n == '\t').is_some() {}
+                if chars.peek().is_some_and(|&n| n != '\n') {
+                    out.push(' ');
+                }
+                continue;
+            }
+            _ => {}
+        }
+        out.push(c);
+    }
+    out
+}
@@ -0,0 +1,153 @@
+//! `--watch`: formats files in place whenever they change, driven by the platform's file
+//! notification events (through `notify`), until interrupted with Ctrl-C.
+
+use crate::log;
+use chuckfmt::FmtError;
+use notify::{RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long a file must go without events before it is formatted, so an editor's burst of
+/// writes is formatted once.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// What a file looked like when it was last formatted (or when watching started).
+type Stamp = (Option<SystemTime>, u64);
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.modified().ok(), meta.len()))
+}
+
+/// What the watch loop is woken up by.
+enum Message {
+    Changed(Vec<PathBuf>),
+    Interrupted,
+}
+
+/// The files `list` returns, keyed by their canonical path (the one events name).
+fn watched<L>(list: &mut L) -> Result<HashMap<PathBuf, PathBuf>, FmtError>
+where
+    L: FnMut() -> Result<Vec<PathBuf>, FmtError>,
+{
+    Ok(list()?
+        .into_iter()
+        .filter_map(|file| Some((fs::canonicalize(&file).ok()?, file)))
+        .collect())
+}
+
+/// Watches `roots` (directories recursively, files through their directory) and calls `format`
+/// on each file `list` returns once it changed, until Ctrl-C. `list` is asked again only when an
+/// event names a file it didn't return, so new files are picked up. Files as they are when
+/// watching starts are left alone. `format` returns whether it rewrote the file; failures are
+/// logged and watching goes on.
+pub fn run<L, F>(roots: &[PathBuf], mut list: L, mut format: F) -> Result<(), FmtError>
+where
+    L: FnMut() -> Result<Vec<PathBuf>, FmtError>,
+    F: FnMut(&Path) -> Result<bool, FmtError>,
+{
+    let watch_err = |e: notify::Error| FmtError::Watch(e.to_string());
+    let (tx, rx) = mpsc::channel();
+    let interrupt = tx.clone();
+    ctrlc::set_handler(move || {
+        let _ = interrupt.send(Message::Interrupted);
+    })
+    .map_err(|e| FmtError::Watch(e.to_string()))?;
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+            // reading a file (ours included) is no change, and must not hold off formatting it
+            Ok(event) if event.kind.is_access() => {}
+            Ok(event) => {
+                let _ = tx.send(Message::Changed(event.paths));
+            }
+            Err(e) => log("watch", &e.to_string()),
+        })
+        .map_err(watch_err)?;
+    for root in roots {
+        // editors often replace a file by renaming over it, which a watch on the file itself
+        // would not survive
+        let (path, mode) = if root.is_dir() {
+            (root.as_path(), RecursiveMode::Recursive)
+        } else {
+            let parent = root.parent().filter(|p| !p.as_os_str().is_empty());
+            (
+                parent.unwrap_or(Path::new(".")),
+                RecursiveMode::NonRecursive,
+            )
+        };
+        watcher.watch(path, mode).map_err(watch_err)?;
+    }
+
+    let mut files = watched(&mut list)?;
+    let mut settled: HashMap<PathBuf, Stamp> = files
+        .keys()
+        .filter_map(|file| Some((file.clone(), stamp(file)?)))
+        .collect();
+    log(
+        "watch",
+        &format!(
+            "watching {} file(s) for changes; press Ctrl-C to stop",
+            settled.len()
+        ),
+    );
+
+    // files with recent events, and when the last one came
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    loop {
+        let wait = pending
+            .values()
+            .map(|last| (*last + DEBOUNCE).saturating_duration_since(Instant::now()))
+            .min()
+            .unwrap_or(Duration::MAX);
+        match rx.recv_timeout(wait) {
+            Ok(Message::Changed(paths)) => {
+                for path in paths {
+                    pending.insert(path, Instant::now());
+                }
+                continue;
+            }
+            Ok(Message::Interrupted) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+        }
+
+        let quiet: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, last)| last.elapsed() >= DEBOUNCE)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in quiet {
+            pending.remove(&path);
+            let Ok(path) = fs::canonicalize(&path) else {
+                continue;
+            };
+            if !files.contains_key(&path) {
+                match watched(&mut list) {
+                    Ok(now) => files = now,
+                    Err(e) => log("watch", &e.to_string()),
+                }
+            }
+            let (Some(file), Some(now)) = (files.get(&path), stamp(&path)) else {
+                continue;
+            };
+            if settled.get(&path) == Some(&now) {
+                continue;
+            }
+
+            let name = file.display().to_string();
+            match format(file) {
+                Ok(true) => log(&name, "formatted"),
+                Ok(false) => log(&name, "already formatted"),
+                Err(e) => log(&name, &e.to_string()),
+            }
+            // our own write must not count as the next change
+            if let Some(after) = stamp(&path) {
+                settled.insert(path, after);
+            }
+        }
+    }
+    log("watch", "stopped");
+    Ok(())
+}
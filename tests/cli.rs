@@ -0,0 +1,1775 @@
+//! Command-line behavior, running the built `chuckfmt` binary with `--no-clang-format`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A fresh scratch directory for one test.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chuckfmt-cli-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn chuckfmt(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+        .arg("--no-clang-format")
+        .args(args)
+        .output()
+        .expect("chuckfmt runs")
+}
+
+/// Writes an executable `clang-format` into `dir` that reports version 18.1.3 and otherwise runs
+/// the shell script `body`.
+#[cfg(unix)]
+fn fake_clang_format(dir: &Path, body: &str) -> PathBuf {
+    fake_clang_format_version(dir, "18.1.3", body)
+}
+
+/// [`fake_clang_format`] reporting `version`.
+#[cfg(unix)]
+fn fake_clang_format_version(dir: &Path, version: &str, body: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let clang = dir.join("clang-format");
+    fs::write(
+        &clang,
+        format!(
+            "#!/bin/sh\n[ \"$1\" = --version ] && echo 'clang-format version {version}' && exit\n{body}"
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&clang, fs::Permissions::from_mode(0o755)).unwrap();
+    clang
+}
+
+#[test]
+fn unique_formats_a_repeated_file_once() {
+    let dir = scratch_dir("unique");
+    let file = dir.join("a.ck");
+    fs::write(&file, "s = > dac;\n").unwrap();
+    let same = dir.join(".").join("a.ck");
+
+    let out = chuckfmt(&["--unique", file.to_str().unwrap(), same.to_str().unwrap()]);
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "s => dac;\n");
+
+    let out = chuckfmt(&[file.to_str().unwrap(), same.to_str().unwrap()]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "s => dac;\ns => dac;\n"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn backup_keeps_the_original_of_changed_files() {
+    let dir = scratch_dir("backup");
+    let changed = dir.join("changed.ck");
+    let clean = dir.join("clean.ck");
+    fs::write(&changed, "s = > dac;\n").unwrap();
+    fs::write(&clean, "s => dac;\n").unwrap();
+
+    let out = chuckfmt(&[
+        "-i",
+        "--backup",
+        changed.to_str().unwrap(),
+        clean.to_str().unwrap(),
+    ]);
+    assert!(out.status.success());
+    assert_eq!(fs::read_to_string(&changed).unwrap(), "s => dac;\n");
+    assert_eq!(
+        fs::read_to_string(dir.join("changed.ck.bak")).unwrap(),
+        "s = > dac;\n"
+    );
+    assert!(!dir.join("clean.ck.bak").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_utf8_is_reported_or_replaced_with_lossy() {
+    let dir = scratch_dir("utf8");
+    let file = dir.join("latin1.ck");
+    fs::write(&file, b"// caf\xe9\ns = > dac;\n").unwrap();
+
+    let out = chuckfmt(&[file.to_str().unwrap()]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("invalid byte at offset 6"), "{stderr}");
+
+    let out = chuckfmt(&["--lossy", file.to_str().unwrap()]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "// caf\u{FFFD}\ns => dac;\n"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn lossy_backup_keeps_the_original_bytes() {
+    let dir = scratch_dir("lossy-backup");
+    let file = dir.join("latin1.ck");
+    fs::write(&file, b"// caf\xe9\ns = > dac;\n").unwrap();
+
+    let out = chuckfmt(&["-i", "--lossy", "--backup", file.to_str().unwrap()]);
+    assert!(out.status.success());
+    assert_eq!(
+        fs::read_to_string(&file).unwrap(),
+        "// caf\u{FFFD}\ns => dac;\n"
+    );
+    assert_eq!(
+        fs::read(dir.join("latin1.ck.bak")).unwrap(),
+        b"// caf\xe9\ns = > dac;\n"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn json_output_reports_each_file() {
+    let dir = scratch_dir("json");
+    let changed = dir.join("changed.ck");
+    let clean = dir.join("clean.ck");
+    fs::write(&changed, "s = > dac;\n").unwrap();
+    fs::write(&clean, "<<< \"hi\" >>>;\n").unwrap();
+
+    let out = chuckfmt(&[
+        "--output-format=json",
+        changed.to_str().unwrap(),
+        clean.to_str().unwrap(),
+    ]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        format!(
+            "[\n  {{\"path\": \"{}\", \"changed\": true, \"formatted\": \"s => dac;\\n\"}},\n  \
+             {{\"path\": \"{}\", \"changed\": false, \"formatted\": \"<<< \\\"hi\\\" >>>;\\n\"}}\n]\n",
+            changed.display(),
+            clean.display()
+        )
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Runs `git` in `dir`, outside any enclosing repository.
+fn git(dir: &std::path::Path, args: &[&str]) -> Output {
+    Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_CEILING_DIRECTORIES", dir.parent().unwrap())
+        .output()
+        .expect("git runs")
+}
+
+#[test]
+fn staged_formats_and_restages_staged_files() {
+    let dir = scratch_dir("staged");
+    if !git(&dir, &["init", "-q"]).status.success() {
+        eprintln!("git not available; skipping");
+        return;
+    }
+    fs::write(dir.join("staged.ck"), "s = > dac;\n").unwrap();
+    fs::write(dir.join("unstaged.ck"), "s = > dac;\n").unwrap();
+    git(&dir, &["add", "staged.ck"]);
+
+    let run = |dir: &std::path::Path| {
+        Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+            .args(["--no-clang-format", "--staged"])
+            .current_dir(dir)
+            .env("GIT_CEILING_DIRECTORIES", dir.parent().unwrap())
+            .output()
+            .expect("chuckfmt runs")
+    };
+    let out = run(&dir);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("staged.ck")).unwrap(),
+        "s => dac;\n"
+    );
+    let index = git(&dir, &["show", ":staged.ck"]);
+    assert_eq!(String::from_utf8_lossy(&index.stdout), "s => dac;\n");
+    assert_eq!(
+        fs::read_to_string(dir.join("unstaged.ck")).unwrap(),
+        "s = > dac;\n"
+    );
+
+    let outside = scratch_dir("staged-no-repo");
+    let out = run(&outside);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("not a git repository"), "{stderr}");
+
+    fs::remove_dir_all(&dir).unwrap();
+    fs::remove_dir_all(&outside).unwrap();
+}
+
+#[test]
+fn check_lists_unformatted_files_and_exits_1() {
+    let dir = scratch_dir("check");
+    let file = dir.join("a.ck");
+    fs::write(&file, "s = > dac;\n").unwrap();
+
+    let out = chuckfmt(&["--check", file.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(out.stdout.is_empty());
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains(&format!("{}\n", file.display())),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(fs::read_to_string(&file).unwrap(), "s = > dac;\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn check_passes_formatted_files_silently() {
+    let dir = scratch_dir("check-clean");
+    let file = dir.join("a.ck");
+    fs::write(&file, "s => dac;\n").unwrap();
+
+    let out = chuckfmt(&["--check", file.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0));
+    assert!(out.stdout.is_empty());
+    assert!(out.stderr.is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn in_place_summary_counts_changed_files() {
+    let dir = scratch_dir("summary");
+    let files: Vec<String> = ["a = > b;\n", "a => b;\n", "c = < d;\n"]
+        .iter()
+        .enumerate()
+        .map(|(i, source)| {
+            let file = dir.join(format!("{i}.ck"));
+            fs::write(&file, source).unwrap();
+            file.to_str().unwrap().to_string()
+        })
+        .collect();
+    let mut args = vec!["-i"];
+    args.extend(files.iter().map(String::as_str));
+
+    let out = chuckfmt(&args);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stderr),
+        "chuckfmt: formatted 2 of 3 files\n"
+    );
+
+    args.insert(0, "--quiet");
+    let out = chuckfmt(&args);
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8_lossy(&out.stderr), "");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn in_place_with_jobs_rewrites_every_file() {
+    let dir = scratch_dir("jobs");
+    let files: Vec<PathBuf> = (0..64)
+        .map(|i| {
+            let path = dir.join(format!("f{i}.ck"));
+            fs::write(&path, format!("x{i} = > y;\n")).unwrap();
+            path
+        })
+        .collect();
+
+    let mut args = vec!["-i", "--jobs", "4"];
+    args.extend(files.iter().map(|f| f.to_str().unwrap()));
+    let out = chuckfmt(&args);
+    assert!(out.status.success());
+    for (i, path) in files.iter().enumerate() {
+        assert_eq!(fs::read_to_string(path).unwrap(), format!("x{i} => y;\n"));
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn in_place_leaves_formatted_files_untouched() {
+    let dir = scratch_dir("untouched");
+    let file = dir.join("a.ck");
+    fs::write(&file, "SinOsc s => dac;\n").unwrap();
+    let past = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+    fs::File::options()
+        .write(true)
+        .open(&file)
+        .unwrap()
+        .set_modified(past)
+        .unwrap();
+
+    let out = chuckfmt(&["-i", file.to_str().unwrap()]);
+    assert!(out.status.success());
+    assert_eq!(fs::metadata(&file).unwrap().modified().unwrap(), past);
+    assert_eq!(fs::read_to_string(&file).unwrap(), "SinOsc s => dac;\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dry_run_lists_files_without_writing() {
+    let dir = scratch_dir("dry-run");
+    let changed = dir.join("changed.ck");
+    let clean = dir.join("clean.ck");
+    fs::write(&changed, "s = > dac;\n").unwrap();
+    fs::write(&clean, "s => dac;\n").unwrap();
+
+    let out = chuckfmt(&[
+        "-i",
+        "--dry-run",
+        "--backup",
+        changed.to_str().unwrap(),
+        clean.to_str().unwrap(),
+    ]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains(&format!("{}\n", changed.display())),
+        "{stderr}"
+    );
+    assert!(!stderr.contains(&clean.display().to_string()), "{stderr}");
+    assert_eq!(fs::read_to_string(&changed).unwrap(), "s = > dac;\n");
+    assert_eq!(fs::read_to_string(&clean).unwrap(), "s => dac;\n");
+    assert!(!dir.join("changed.ck.bak").exists());
+
+    let out = chuckfmt(&["-i", "-n", "--diff", changed.to_str().unwrap()]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("+s => dac;\n"));
+    assert_eq!(fs::read_to_string(&changed).unwrap(), "s = > dac;\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn diff_of_one_changed_line_is_a_single_hunk() {
+    let dir = scratch_dir("diff-one-line");
+    let file = dir.join("a.ck");
+    let source = "a => b;\nc => d;\ne = > f;\ng => h;\nk => l;\nm => n;\no => p;\nq => r;\n";
+    fs::write(&file, source).unwrap();
+
+    let out = chuckfmt(&["--diff", file.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(1));
+    let name = file.display();
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        format!(
+            "--- {name}\n+++ {name} (formatted)\n@@ -1,6 +1,6 @@\n a => b;\n c => d;\n\
+             -e = > f;\n+e => f;\n g => h;\n k => l;\n m => n;\n"
+        )
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn diff_of_a_formatted_file_is_empty() {
+    let dir = scratch_dir("diff-clean");
+    let file = dir.join("a.ck");
+    fs::write(&file, "a => b;\n").unwrap();
+
+    let out = chuckfmt(&["--diff", file.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0));
+    assert!(out.stdout.is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn diff_of_a_large_rewrite_runs_in_little_memory() {
+    let dir = scratch_dir("large-diff");
+    let file = dir.join("big.ck");
+    // every line changes, with a repeated line in between that stays
+    let source: String = (0..20_000).map(|i| format!("x{i} = > y;\n}}\n")).collect();
+    fs::write(&file, &source).unwrap();
+
+    // a 1 GB address space is far more than the diff needs
+    let out = Command::new("sh")
+        .arg("-c")
+        .arg("ulimit -v 1000000 && exec \"$0\" --no-clang-format --diff \"$1\"")
+        .arg(env!("CARGO_BIN_EXE_chuckfmt"))
+        .arg(&file)
+        .output()
+        .unwrap();
+    assert_eq!(
+        out.status.code(),
+        Some(1),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let patch = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(patch.lines().filter(|l| l.starts_with('-')).count(), 20_001);
+    assert_eq!(patch.lines().filter(|l| l.starts_with('+')).count(), 20_001);
+    assert!(
+        patch.contains("-x19999 = > y;\n+x19999 => y;\n }\n"),
+        "{patch}"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn directory_walks_skip_ignored_files_unless_no_ignore() {
+    let dir = scratch_dir("ignore");
+    fs::create_dir_all(dir.join("build")).unwrap();
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join(".gitignore"), "# generated\nbuild/\n").unwrap();
+    fs::write(dir.join("src/.ignore"), "*.gen.ck\n!keep.gen.ck\n").unwrap();
+    for name in [
+        "main.ck",
+        "build/out.ck",
+        "src/osc.gen.ck",
+        "src/keep.gen.ck",
+    ] {
+        fs::write(dir.join(name), "s = > dac;\n").unwrap();
+    }
+
+    let out = chuckfmt(&["-i", dir.to_str().unwrap()]);
+    assert!(out.status.success());
+    assert_eq!(
+        fs::read_to_string(dir.join("main.ck")).unwrap(),
+        "s => dac;\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("src/keep.gen.ck")).unwrap(),
+        "s => dac;\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("build/out.ck")).unwrap(),
+        "s = > dac;\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("src/osc.gen.ck")).unwrap(),
+        "s = > dac;\n"
+    );
+
+    let out = chuckfmt(&["-i", "--no-ignore", dir.to_str().unwrap()]);
+    assert!(out.status.success());
+    assert_eq!(
+        fs::read_to_string(dir.join("build/out.ck")).unwrap(),
+        "s => dac;\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("src/osc.gen.ck")).unwrap(),
+        "s => dac;\n"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn directory_walks_recognize_chuck_extensions() {
+    let dir = scratch_dir("extensions");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    for name in ["main.ck", "lib/osc.chuck", "lib/notes.txt", "lib/util.ckx"] {
+        fs::write(dir.join(name), "s = > dac;\n").unwrap();
+    }
+    let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+    let reset = || {
+        for name in ["main.ck", "lib/osc.chuck", "lib/util.ckx"] {
+            fs::write(dir.join(name), "s = > dac;\n").unwrap();
+        }
+    };
+
+    // .ck and .chuck by default
+    let out = chuckfmt(&["-i", dir.to_str().unwrap()]);
+    assert!(out.status.success());
+    assert_eq!(read("main.ck"), "s => dac;\n");
+    assert_eq!(read("lib/osc.chuck"), "s => dac;\n");
+    assert_eq!(read("lib/util.ckx"), "s = > dac;\n");
+    assert_eq!(read("lib/notes.txt"), "s = > dac;\n");
+
+    // --extension replaces the defaults and can be given several times
+    reset();
+    let out = chuckfmt(&[
+        "-i",
+        "--extension",
+        "ckx",
+        "--extension=.chuck",
+        dir.to_str().unwrap(),
+    ]);
+    assert!(out.status.success());
+    assert_eq!(read("main.ck"), "s = > dac;\n");
+    assert_eq!(read("lib/osc.chuck"), "s => dac;\n");
+    assert_eq!(read("lib/util.ckx"), "s => dac;\n");
+
+    // so does `extensions` in .chuckfmt.toml, for walks and --only-ck alike
+    reset();
+    fs::write(dir.join(".chuckfmt.toml"), "extensions = [\"ckx\"]\n").unwrap();
+    let out = chuckfmt(&[
+        "-i",
+        "--only-ck",
+        dir.to_str().unwrap(),
+        dir.join("main.ck").to_str().unwrap(),
+    ]);
+    assert!(out.status.success());
+    assert_eq!(read("lib/util.ckx"), "s => dac;\n");
+    assert_eq!(read("lib/osc.chuck"), "s = > dac;\n");
+    assert_eq!(read("main.ck"), "s = > dac;\n");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("skipped: not a .ckx file"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn assume_language_picks_the_assume_filename_extension() {
+    let dir = scratch_dir("assume-language");
+    // a clang-format that reports the arguments it was given and formats nothing
+    let clang = fake_clang_format(&dir, "echo \"$@\" >&2\ncat\n");
+    let file = dir.join("a.ck");
+    fs::write(&file, "s => dac;\n").unwrap();
+
+    let run = |args: &[&str]| {
+        let out = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+            .arg("--clang-format-path")
+            .arg(&clang)
+            .args(args)
+            .arg(&file)
+            .output()
+            .expect("chuckfmt runs");
+        assert!(out.status.success(), "{out:?}");
+        String::from_utf8(out.stderr).unwrap()
+    };
+    let assumed = |ext: &str| format!("--assume-filename={}.{ext}", file.display());
+
+    assert!(run(&[]).contains(&assumed("java")));
+    assert!(run(&["--assume-language=cpp"]).contains(&assumed("cpp")));
+    assert!(run(&["--assume-language", "java"]).contains(&assumed("java")));
+
+    fs::write(dir.join(".chuckfmt.toml"), "language = \"cpp\"\n").unwrap();
+    assert!(run(&[]).contains(&assumed("cpp")));
+    assert!(run(&["--assume-language=java"]).contains(&assumed("java")));
+    // an explicit --assume-filename always wins
+    let stderr = run(&["--assume-filename=x.cs"]);
+    assert!(stderr.contains("--assume-filename=x.cs"), "{stderr}");
+    assert!(!stderr.contains(".cpp"), "{stderr}");
+    // ... but a ChucK name still gets the language's extension, or clang-format would use C++
+    let stderr = run(&["--assume-filename=foo.ck"]);
+    assert!(stderr.contains("--assume-filename=foo.ck.cpp"), "{stderr}");
+    let stderr = run(&["--assume-language=java", "--assume-filename", "foo.ck"]);
+    assert!(stderr.contains("--assume-filename foo.ck.java"), "{stderr}");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn style_chuck_passes_the_embedded_style_to_clang_format() {
+    let dir = scratch_dir("style-chuck");
+    let clang = fake_clang_format(&dir, "echo \"$@\" >&2\ncat\n");
+
+    let out = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+        .arg("--clang-format-path")
+        .arg(&clang)
+        .args(["--style=chuck", "--stdin-string", "s => dac;"])
+        .output()
+        .expect("chuckfmt runs");
+    assert!(out.status.success(), "{out:?}");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains(&format!("--style={}", chuckfmt::CHUCK_STYLE)),
+        "{stderr}"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn configured_assume_filename_reaches_clang_format() {
+    let dir = scratch_dir("assume-filename-config");
+    let clang = fake_clang_format(&dir, "echo \"$@\" >&2\ncat\n");
+    let file = dir.join("a.ck");
+    fs::write(&file, "s => dac;\n").unwrap();
+    fs::write(
+        dir.join(".chuckfmt.toml"),
+        "assume_filename = \"chuck.java\"\n",
+    )
+    .unwrap();
+
+    let run = |args: &[&str]| {
+        let out = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+            .arg("--clang-format-path")
+            .arg(&clang)
+            .current_dir(&dir)
+            .args(args)
+            .output()
+            .expect("chuckfmt runs");
+        assert!(out.status.success(), "{out:?}");
+        String::from_utf8(out.stderr).unwrap()
+    };
+
+    // in place of the file's own name, in its directory; and of `code` for stdin
+    let stderr = run(&[file.to_str().unwrap()]);
+    assert!(
+        stderr.contains(&format!(
+            "--assume-filename={}\n",
+            dir.join("chuck.java").display()
+        )),
+        "{stderr}"
+    );
+    let stderr = run(&["--stdin-string", "s => dac;"]);
+    assert!(
+        stderr.contains("--assume-filename=chuck.java\n"),
+        "{stderr}"
+    );
+    // the language's extension still comes from `language`
+    let stderr = run(&["--assume-language=cpp", "--stdin-string", "s => dac;"]);
+    assert!(stderr.contains("--assume-filename=chuck.cpp\n"), "{stderr}");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn empty_input_does_not_run_clang_format() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = scratch_dir("empty");
+    // a clang-format that leaves a marker behind whenever it is asked to format
+    let marker = dir.join("ran");
+    let clang = fake_clang_format(&dir, &format!("touch '{}'\ncat\n", marker.display()));
+    let command = || {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_chuckfmt"));
+        command.arg("--clang-format-path").arg(&clang);
+        command
+    };
+
+    let mut child = command()
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"").unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    assert!(out.stdout.is_empty());
+
+    let file = dir.join("empty.ck");
+    fs::write(&file, "").unwrap();
+    let out = command().arg("-i").arg(&file).output().unwrap();
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(fs::read_to_string(&file).unwrap(), "");
+    assert!(!marker.exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn only_ck_skips_files_that_are_not_chuck() {
+    let dir = scratch_dir("only-ck");
+    let chuck = dir.join("a.ck");
+    let text = dir.join("notes.txt");
+    fs::write(&chuck, "s = > dac;\n").unwrap();
+    fs::write(&text, "s = > dac;\n").unwrap();
+
+    let out = chuckfmt(&[
+        "-i",
+        "--only-ck",
+        chuck.to_str().unwrap(),
+        text.to_str().unwrap(),
+    ]);
+    assert!(out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains(&format!("{}: skipped", text.display())),
+        "{stderr}"
+    );
+    assert_eq!(fs::read_to_string(&chuck).unwrap(), "s => dac;\n");
+    assert_eq!(fs::read_to_string(&text).unwrap(), "s = > dac;\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn stdin_flag_ignores_path_like_arguments() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = scratch_dir("stdin-flag");
+    let file = dir.join("a.ck");
+    fs::write(&file, "x = > y;\n").unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+        .args(["--no-clang-format", "--stdin"])
+        .arg(&file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"s = > dac;\n")
+        .unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "s => dac;\n");
+    assert!(
+        String::from_utf8_lossy(&out.stderr)
+            .contains(&format!("{}: ignored: --stdin", file.display()))
+    );
+    assert_eq!(fs::read_to_string(&file).unwrap(), "x = > y;\n");
+
+    // files given explicitly are an error rather than silently dropped
+    let out = chuckfmt(&["--stdin", "--", file.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(2));
+    let out = chuckfmt(&["--stdin", "-i"]);
+    assert_eq!(out.status.code(), Some(2));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn warn_suspicious_reports_an_unbalanced_print() {
+    let dir = scratch_dir("warn-suspicious");
+    let file = dir.join("a.ck");
+    fs::write(&file, "SinOsc s => dac;\n<<< s.freq();\n").unwrap();
+
+    let out = chuckfmt(&["--warn-suspicious", file.to_str().unwrap()]);
+    assert!(out.status.success());
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains(&format!(
+            "{}:2: warning: `<<<` is never closed by a `>>>`",
+            file.display()
+        )),
+        "{out:?}"
+    );
+
+    // only when asked for
+    let out = chuckfmt(&[file.to_str().unwrap()]);
+    assert!(out.stderr.is_empty(), "{out:?}");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn transforms_only_keeps_manual_layout() {
+    let input = "fun void f() {\n      s.freq()\n              = > float x;\n  if (x > 1)\n          1 = > x;\n}\n";
+    let out = chuckfmt(&["--transforms-only", "--stdin-string", input]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "fun void f() {\n      s.freq()\n              => float x;\n  if (x > 1)\n          1 => x;\n}\n"
+    );
+}
+
+#[test]
+fn piped_stdin_is_read_without_a_hint() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+        .arg("--no-clang-format")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"s = > dac;\n")
+        .unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    assert!(out.stderr.is_empty(), "{out:?}");
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "s => dac;\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn stdin_filename_picks_the_config_and_style_of_its_directory() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = scratch_dir("stdin-filename");
+    let project = dir.join("project");
+    fs::create_dir_all(project.join("src")).unwrap();
+    fs::write(
+        project.join(".chuckfmt.toml"),
+        "[transforms]\nprint_brackets = false\n",
+    )
+    .unwrap();
+    // prints the options it gets, then formats nothing
+    let clang = fake_clang_format(&dir, "echo \"$@\" >&2\ncat\n");
+    let run = |args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+            .current_dir(&dir)
+            .arg("--clang-format-path")
+            .arg(&clang)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(b"<<<x>>>;\ns = > dac;\n").unwrap();
+        drop(stdin);
+        let out = child.wait_with_output().unwrap();
+        assert!(out.status.success(), "{out:?}");
+        (
+            String::from_utf8(out.stdout).unwrap(),
+            String::from_utf8(out.stderr).unwrap(),
+        )
+    };
+
+    // clang-format finds the project's .clang-format (and .clang-format-ignore) from the
+    // assumed name, and chuckfmt the project's .chuckfmt.toml
+    let (stdout, stderr) = run(&["--stdin-filename", "project/src/a.ck"]);
+    assert_eq!(stdout, "<<<x>>>;\ns => dac;\n");
+    assert!(
+        stderr.contains("--assume-filename=project/src/a.ck.java"),
+        "{stderr}"
+    );
+
+    let (stdout, stderr) = run(&[]);
+    assert_eq!(stdout, "<<< x >>>;\ns => dac;\n");
+    assert!(stderr.contains("--assume-filename=code.java"), "{stderr}");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn terminal_stdin_without_files_prints_a_hint() {
+    // util-linux `script` runs the command with a pseudo-terminal as its stdin
+    let command = format!("{} --no-clang-format", env!("CARGO_BIN_EXE_chuckfmt"));
+    let Ok(out) = Command::new("script")
+        .args(["-qec", &command, "/dev/null"])
+        .stdin(std::process::Stdio::null())
+        .output()
+    else {
+        return;
+    };
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+    assert!(
+        String::from_utf8_lossy(&out.stdout).contains("stdin is a terminal"),
+        "{out:?}"
+    );
+}
+
+#[test]
+fn dash_reads_stdin_between_files() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = scratch_dir("dash");
+    let first = dir.join("first.ck");
+    let last = dir.join("last.ck");
+    fs::write(&first, "a = > b;\n").unwrap();
+    fs::write(&last, "e = > f;\n").unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+        .arg("--no-clang-format")
+        .arg(&first)
+        .arg("-")
+        .arg(&last)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"c = > d;\n")
+        .unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "a => b;\nc => d;\ne => f;\n"
+    );
+
+    let out = chuckfmt(&["-i", "-", first.to_str().unwrap()]);
+    assert!(!out.status.success());
+    assert_eq!(fs::read_to_string(&first).unwrap(), "a = > b;\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn range_stdin_formats_only_the_middle_of_the_buffer() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let buffer = "a = > b;\nc = > d;\ne = > f;\n";
+    // bytes 9..17 are the second line, without its newline
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+        .args(["--no-clang-format", "--range-stdin", "9:17"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(buffer.as_bytes())
+        .unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "a = > b;\nc => d;\ne = > f;\n"
+    );
+
+    let out = chuckfmt(&["--range-stdin=9:3"]);
+    assert!(!out.status.success());
+    let out = chuckfmt(&["--range-stdin=0:3", "foo.ck"]);
+    assert!(!out.status.success());
+}
+
+#[test]
+fn missing_file_in_list_is_reported_with_its_line() {
+    let dir = scratch_dir("files-list");
+    let present = dir.join("present.ck");
+    let missing = dir.join("missing.ck");
+    let list = dir.join("list.txt");
+    fs::write(&present, "s = > dac;\n").unwrap();
+    fs::write(
+        &list,
+        format!("{}\n\n{}\n", present.display(), missing.display()),
+    )
+    .unwrap();
+
+    let out = chuckfmt(&["-i", "--files", list.to_str().unwrap()]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "failed to read {} (line 3 of --files list '{}')",
+            missing.display(),
+            list.display()
+        )),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("1 of 2 file(s) could not be formatted"),
+        "{stderr}"
+    );
+    // the rest of the list is still formatted
+    assert_eq!(fs::read_to_string(&present).unwrap(), "s => dac;\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn print_clang_command_shows_the_injected_assume_filename() {
+    let dir = scratch_dir("print-command");
+    let clang = fake_clang_format(&dir, "cat\n");
+    let file = dir.join("a.ck");
+    fs::write(&file, "s => dac;\n").unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+        .arg("--clang-format-path")
+        .arg(&clang)
+        .args(["--print-clang-command", "--style={BasedOnStyle: Google}"])
+        .arg(&file)
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stderr.contains(&format!(
+            "clang-format command: {} '--style={{BasedOnStyle: Google}}' --assume-filename={}.java",
+            clang.display(),
+            file.display()
+        )),
+        "{stderr}"
+    );
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "s => dac;\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn double_dash_ends_options_with_files_on_both_sides() {
+    let dir = scratch_dir("double-dash");
+    let clang = fake_clang_format(&dir, "cat\n");
+    let [before, after] = ["before.ck", "-after.ck"].map(|name| {
+        let path = dir.join(name);
+        fs::write(&path, "s = > dac;\n").unwrap();
+        path
+    });
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+            .arg("--clang-format-path")
+            .arg(&clang)
+            .current_dir(&dir)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    // files before and after `--`, options before it; a file after it may start with `-`
+    let out = run(&[
+        "before.ck",
+        "-style=LLVM",
+        "--print-clang-command",
+        "-i",
+        "--",
+        "-after.ck",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    for name in ["before.ck", "-after.ck"] {
+        assert!(
+            stderr.contains(&format!(
+                "clang-format command: {} -style=LLVM --assume-filename={name}.java\n",
+                clang.display()
+            )),
+            "{stderr}"
+        );
+    }
+    for path in [&before, &after] {
+        assert_eq!(fs::read_to_string(path).unwrap(), "s => dac;\n");
+    }
+
+    // an option after `--` is refused, not handed to clang-format as a file
+    fs::write(&before, "s = > dac;\n").unwrap();
+    let out = run(&["-i", "before.ck", "--", "--style=LLVM"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--style=LLVM: no such file"));
+    assert_eq!(fs::read_to_string(&before).unwrap(), "s = > dac;\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn output_writes_the_result_to_a_new_file() {
+    let dir = scratch_dir("output");
+    let source = dir.join("a.ck");
+    let target = dir.join("formatted.ck");
+    fs::write(&source, "SinOsc s = > dac;\n<<<s.freq()>>>;\n").unwrap();
+
+    let out = chuckfmt(&[source.to_str().unwrap(), "-o", target.to_str().unwrap()]);
+    assert!(out.status.success(), "{out:?}");
+    assert!(out.stdout.is_empty());
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        "SinOsc s => dac;\n<<< s.freq() >>>;\n"
+    );
+    assert_eq!(
+        fs::read_to_string(&source).unwrap(),
+        "SinOsc s = > dac;\n<<<s.freq()>>>;\n"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn write_failures_name_the_failing_step() {
+    let dir = scratch_dir("write-steps");
+    // renaming a file over a directory fails even for root
+    let target = dir.join("out.ck");
+    fs::create_dir(&target).unwrap();
+
+    let out = chuckfmt(&[
+        "--stdin-string",
+        "s = > dac;",
+        "-o",
+        target.to_str().unwrap(),
+    ]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains(&format!("failed to write {}: renaming ", target.display())),
+        "{stderr}"
+    );
+    assert!(stderr.contains(" over it: "), "{stderr}");
+    // the temporary file is cleaned up
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn read_only_directory_is_reported_as_such() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = scratch_dir("read-only");
+    let file = dir.join("a.ck");
+    fs::write(&file, "s = > dac;\n").unwrap();
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o555)).unwrap();
+    // permissions don't stop root, so there is nothing to check then
+    let writable = fs::write(dir.join("probe"), "").is_ok();
+
+    let out = chuckfmt(&["-i", file.to_str().unwrap()]);
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+    if !writable {
+        assert!(!out.status.success());
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(
+            stderr.contains(&format!(
+                "failed to write {}: creating temporary file",
+                file.display()
+            )),
+            "{stderr}"
+        );
+        assert!(
+            stderr.contains(&format!("(check that {} is writable)", dir.display())),
+            "{stderr}"
+        );
+        assert_eq!(fs::read_to_string(&file).unwrap(), "s = > dac;\n");
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn in_place_writes_through_a_symlink() {
+    let dir = scratch_dir("symlink");
+    let real = dir.join("real.ck");
+    let link = dir.join("link.ck");
+    fs::write(&real, "a = > b;\n").unwrap();
+    std::os::unix::fs::symlink(&real, &link).unwrap();
+
+    let out = chuckfmt(&["-i", link.to_str().unwrap()]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(
+        fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink()
+    );
+    assert_eq!(fs::read_to_string(&real).unwrap(), "a => b;\n");
+    // no temporary file is left next to either
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn in_place_keeps_file_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = scratch_dir("permissions");
+    let file = dir.join("a.ck");
+    fs::write(&file, "a = > b;\n").unwrap();
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+
+    let out = chuckfmt(&["-i", file.to_str().unwrap()]);
+    assert!(out.status.success());
+    assert_eq!(fs::read_to_string(&file).unwrap(), "a => b;\n");
+    let mode = fs::metadata(&file).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o640);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn every_files_list_is_read() {
+    let dir = scratch_dir("two-lists");
+    let [a, b, c] = ["a.ck", "b.ck", "c.ck"].map(|name| {
+        let path = dir.join(name);
+        fs::write(&path, "s = > dac;\n").unwrap();
+        path
+    });
+    let first = dir.join("first.txt");
+    let second = dir.join("second.txt");
+    fs::write(&first, format!("{}\n", a.display())).unwrap();
+    fs::write(&second, format!("{}\n{}\n", b.display(), c.display())).unwrap();
+
+    let first_opt = format!("--files={}", first.display());
+    let out = chuckfmt(&["-i", &first_opt, "-files", second.to_str().unwrap()]);
+    assert!(out.status.success());
+    for path in [a, b, c] {
+        assert_eq!(fs::read_to_string(&path).unwrap(), "s => dac;\n");
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn files_list_piped_on_stdin_is_formatted_in_place() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = scratch_dir("stdin-list");
+    let files: Vec<PathBuf> = (0..5)
+        .map(|i| {
+            let path = dir.join(format!("f{i}.ck"));
+            fs::write(&path, "s = > dac;\n").unwrap();
+            path
+        })
+        .collect();
+    let list: String = files.iter().map(|f| format!("{}\n", f.display())).collect();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+        .args(["--no-clang-format", "-i", "--files", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(list.as_bytes())
+        .unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    for path in &files {
+        assert_eq!(fs::read_to_string(path).unwrap(), "s => dac;\n");
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn files_list_entries_are_never_expanded_as_lists() {
+    let dir = scratch_dir("nested-lists");
+    let source = dir.join("a.ck");
+    fs::write(&source, "s = > dac;\n").unwrap();
+    let nested = dir.join("nested.txt");
+    fs::write(&nested, format!("{}\n", source.display())).unwrap();
+    let list = dir.join("list.txt");
+    fs::write(&list, format!("{}\n", nested.display())).unwrap();
+
+    // a line naming another list is a source file like any other: it is printed, not expanded
+    let out = chuckfmt(&["--files", list.to_str().unwrap()]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        format!("{}\n", source.display())
+    );
+
+    // a list naming itself is refused, so -i can't overwrite it
+    let contents = format!("{}\n{}\n", source.display(), list.display());
+    fs::write(&list, &contents).unwrap();
+    let out = chuckfmt(&["-i", "--files", list.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains(&format!("{}:2:", list.display())),
+        "{out:?}"
+    );
+    assert_eq!(fs::read_to_string(&list).unwrap(), contents);
+    assert_eq!(fs::read_to_string(&source).unwrap(), "s = > dac;\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn files_list_and_style_values_are_not_mixed_up() {
+    let dir = scratch_dir("files-style");
+    let clang = fake_clang_format(&dir, "cat\n");
+    let file = dir.join("a.ck");
+    let list = dir.join("list.txt");
+    fs::write(&list, format!("{}\n", file.display())).unwrap();
+    let list = list.to_str().unwrap();
+
+    for (args, style) in [
+        (vec!["--style=foo", "--files", list], "--style=foo"),
+        (vec!["--files", list, "--style", "foo"], "--style foo"),
+        (vec!["-style", "foo", "-files", list], "-style foo"),
+    ] {
+        fs::write(&file, "s = > dac;\n").unwrap();
+        let out = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+            .arg("--clang-format-path")
+            .arg(&clang)
+            .args(["--print-clang-command", "-i"])
+            .args(&args)
+            .output()
+            .unwrap();
+        assert!(out.status.success(), "{args:?}: {out:?}");
+        let stderr = String::from_utf8(out.stderr).unwrap();
+        assert!(
+            stderr.contains(&format!(
+                "clang-format command: {} {style} --assume-filename={}.java\n",
+                clang.display(),
+                file.display()
+            )),
+            "{args:?}: {stderr}"
+        );
+        assert_eq!(fs::read_to_string(&file).unwrap(), "s => dac;\n");
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_clang_format_path_is_reported() {
+    let dir = scratch_dir("missing-clang-format");
+    let clang = dir.join("no-such-clang-format");
+
+    let out = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+        .arg("--clang-format-path")
+        .arg(&clang)
+        .args(["--stdin-string", "s => dac;"])
+        .output()
+        .expect("chuckfmt runs");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(out.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "--clang-format-path is set but not executable: {}",
+            clang.display()
+        )),
+        "{stderr}"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn require_version_rejects_an_old_clang_format() {
+    let dir = scratch_dir("require-version");
+    let clang = fake_clang_format_version(&dir, "11.0.1", "cat\n");
+    let file = dir.join("a.ck");
+    fs::write(&file, "s = > dac;\n").unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+            .arg("--clang-format-path")
+            .arg(&clang)
+            .args(args)
+            .arg(&file)
+            .output()
+            .unwrap()
+    };
+
+    // only a warning by default
+    let out = run(&[]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains(
+        "warning: clang-format 11 is older than the minimum supported version 14; \
+             formatting may differ"
+    ));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "s => dac;\n");
+
+    let out = run(&["--require-version", "-i"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&out.stderr),
+        "chuckfmt: clang-format 11 is older than the minimum supported version 14\n"
+    );
+    assert_eq!(fs::read_to_string(&file).unwrap(), "s = > dac;\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn fail_on_warnings_fails_when_clang_format_warns() {
+    let dir = scratch_dir("fail-on-warnings");
+    // formats fine and exits 0, but warns
+    let clang = fake_clang_format(&dir, "echo 'warning: unterminated construct' >&2\ncat\n");
+    let file = dir.join("a.ck");
+    fs::write(&file, "s = > dac;\n").unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+            .arg("--clang-format-path")
+            .arg(&clang)
+            .args(args)
+            .arg(&file)
+            .output()
+            .unwrap()
+    };
+
+    let out = run(&[]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("warning: unterminated construct"));
+
+    let out = run(&["--fail-on-warnings", "-i"]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("clang-format printed warnings"), "{stderr}");
+    assert_eq!(fs::read_to_string(&file).unwrap(), "s = > dac;\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn check_install_reports_each_step() {
+    let dir = scratch_dir("check-install");
+    let clang = fake_clang_format(&dir, "cat\n");
+    let run = |path: &Path| {
+        Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+            .arg("--check-install")
+            .arg("--clang-format-path")
+            .arg(path)
+            .output()
+            .unwrap()
+    };
+
+    let out = run(&clang);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{stdout}");
+    assert!(
+        stdout.contains("version       ok: clang-format version 18.1.3"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("round trip    ok"), "{stdout}");
+    assert!(stdout.contains("ready to use"), "{stdout}");
+
+    let out = run(&dir.join("missing"));
+    assert!(!out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("clang-format  FAIL"), "{stdout}");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("install check failed: clang-format step failed"),
+        "{stderr}"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn keep_blank_lines_sets_max_empty_lines_to_keep() {
+    let dir = scratch_dir("keep-blank-lines");
+    // squeezes runs of blank lines down to MaxEmptyLinesToKeep (1 if the style doesn't say)
+    let clang = fake_clang_format(
+        &dir,
+        "keep=1\nfor a; do case \"$a\" in *MaxEmptyLinesToKeep:*) keep=$(echo \"$a\" | sed 's/.*MaxEmptyLinesToKeep: *\\([0-9]*\\).*/\\1/');; esac; done\nawk -v keep=\"$keep\" '/^$/ { if (++n > keep) next; print; next } { n = 0; print }'\n",
+    );
+    let file = dir.join("a.ck");
+    fs::write(
+        &file,
+        "SinOsc s = > dac;\n\n\n\nfun void f() {\n    spork ~ g();\n}\n\n\n\n<<< 1 >>>;\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+            .arg("--clang-format-path")
+            .arg(&clang)
+            .args(args)
+            .arg(&file)
+            .output()
+            .unwrap()
+    };
+
+    let out = run(&["--keep-blank-lines", "2"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "SinOsc s => dac;\n\n\nfun void f() {\n    spork ~ g();\n}\n\n\n<<< 1 >>>;\n"
+    );
+
+    let out = run(&[]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "SinOsc s => dac;\n\nfun void f() {\n    spork ~ g();\n}\n\n<<< 1 >>>;\n"
+    );
+
+    let out = run(&["--keep-blank-lines=many"]);
+    assert!(!out.status.success());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn stdin_string_formats_the_argument() {
+    let out = chuckfmt(&["--stdin-string", "SinOsc s = > dac; 1 ::second => now;"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "SinOsc s => dac; 1::second => now;\n"
+    );
+
+    let out = chuckfmt(&["--stdin-string", "<<<x>>>;\n", "--check"]);
+    assert!(!out.status.success());
+
+    let out = chuckfmt(&["--stdin-string", "x => y;", "a.ck"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--stdin-string"));
+}
+
+#[test]
+fn normalize_unicode_operators_rewrites_arrows() {
+    let dir = scratch_dir("unicode-operators");
+    let file = dir.join("a.ck");
+    fs::write(&file, "SinOsc s ⇒ dac;\n1::second ⟹ now;\n").unwrap();
+
+    // left as written without the flag
+    let out = chuckfmt(&["--check", file.to_str().unwrap()]);
+    assert!(out.status.success());
+
+    let out = chuckfmt(&[
+        "--normalize-unicode-operators",
+        "-i",
+        file.to_str().unwrap(),
+    ]);
+    assert!(out.status.success());
+    assert_eq!(
+        fs::read_to_string(&file).unwrap(),
+        "SinOsc s => dac;\n1::second => now;\n"
+    );
+
+    let out = chuckfmt(&[
+        "--normalize-unicode-operators",
+        "-offset=3",
+        file.to_str().unwrap(),
+    ]);
+    assert_eq!(out.status.code(), Some(2));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn chuckfmt_off_region_is_kept_from_clang_format() {
+    let dir = scratch_dir("chuckfmt-off");
+    // squeezes runs of spaces, except between clang-format's own off and on comments
+    let clang = fake_clang_format(
+        &dir,
+        "sed '/clang-format off/,/clang-format on/!s/\\([^ ]\\)  */\\1 /g'\n",
+    );
+    let file = dir.join("a.ck");
+    let aligned =
+        "// chuckfmt: off\nSinOsc a   =>  Gain g  => dac;\nTriOsc bb  =>  g;\n// chuckfmt: on\n";
+    fs::write(
+        &file,
+        format!("SinOsc  s =>  dac;\n{aligned}1::second  =>  now;\n"),
+    )
+    .unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+        .arg("--clang-format-path")
+        .arg(&clang)
+        .arg(&file)
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        format!("SinOsc s => dac;\n{aligned}1::second => now;\n")
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn skip_marked_file_is_left_alone_in_place() {
+    let dir = scratch_dir("skip-marked");
+    let generated = dir.join("generated.ck");
+    let source = "// chuckfmt: skip\n// generated, do not edit\nSinOsc s = > dac;   \n";
+    fs::write(&generated, source).unwrap();
+    let other = dir.join("other.ck");
+    fs::write(&other, "SinOsc s = > dac;\n").unwrap();
+
+    let out = chuckfmt(&["-i", dir.to_str().unwrap()]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(fs::read(&generated).unwrap(), source.as_bytes());
+    assert_eq!(fs::read_to_string(&other).unwrap(), "SinOsc s => dac;\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn spacing_flag_overrides_the_config() {
+    let dir = scratch_dir("spacing");
+    fs::write(dir.join(".chuckfmt.toml"), "[spacing]\nchuck = \"tight\"\n").unwrap();
+    let file = dir.join("a.ck");
+    fs::write(&file, "SinOsc s = > Gain g = > dac;\n<<< g.gain() >>>;\n").unwrap();
+
+    let out = chuckfmt(&[file.to_str().unwrap()]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "SinOsc s=>Gain g=>dac;\n<<< g.gain() >>>;\n"
+    );
+
+    let out = chuckfmt(&[
+        "--spacing",
+        "chuck=spaced,print=tight",
+        file.to_str().unwrap(),
+    ]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "SinOsc s => Gain g => dac;\n<<<g.gain()>>>;\n"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn markdown_formats_only_chuck_blocks() {
+    let dir = scratch_dir("markdown");
+    let file = dir.join("README.md");
+    let doc = "# Demo\n\nPrint <<<\"hi\">>> like this:\n\n```chuck\n<<<\"hi\">>>;\n```\n\n\
+               1. then\n\n   ```ck title=\"x\"\n   <<<1>>>;\n   ```\n\n\
+               ````md\n```chuck\n<<<\"as written\">>>;\n```\n````\n";
+    fs::write(&file, doc).unwrap();
+
+    let out = chuckfmt(&["-i", "--markdown", file.to_str().unwrap()]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(
+        fs::read_to_string(&file).unwrap(),
+        doc.replace("<<<\"hi\">>>;", "<<< \"hi\" >>>;")
+            .replace("<<<1>>>", "<<< 1 >>>")
+    );
+
+    // directories are searched for Markdown instead of ChucK files
+    fs::write(dir.join("a.ck"), "<<<1>>>;\n").unwrap();
+    let out = chuckfmt(&["--check", "--markdown", dir.to_str().unwrap()]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+
+    let out = chuckfmt(&["--markdown", "-lines=1:2", file.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn verbose_logs_the_transforms_that_fired() {
+    let dir = scratch_dir("verbose");
+    let changed = dir.join("changed.ck");
+    let clean = dir.join("clean.ck");
+    fs::write(&changed, "s = > dac;\n<<<x>>>;\n").unwrap();
+    fs::write(&clean, "s => dac;\n").unwrap();
+
+    let out = chuckfmt(&[
+        "--verbose",
+        changed.to_str().unwrap(),
+        clean.to_str().unwrap(),
+    ]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "s => dac;\n<<< x >>>;\ns => dac;\n"
+    );
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "chuckfmt: {}: transforms: chuck_arrow, print_brackets\n",
+            changed.display()
+        )),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!(
+            "chuckfmt: {}: transforms: none\n",
+            clean.display()
+        )),
+        "{stderr}"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn help_prints_usage_and_exits_0() {
+    for flag in ["--help", "-h"] {
+        // files and other options don't matter once help is asked for
+        let out = chuckfmt(&["-i", flag, "missing.ck"]);
+        assert_eq!(out.status.code(), Some(0));
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        assert!(stdout.starts_with("Usage: chuckfmt [OPTIONS]"), "{stdout}");
+        assert!(stdout.contains("\n  --check "), "{stdout}");
+        assert!(out.stderr.is_empty());
+    }
+}
+
+#[test]
+fn usage_errors_exit_with_2() {
+    let out = chuckfmt(&["-i"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8_lossy(&out.stderr),
+        "chuckfmt: -i requires at least one file\n"
+    );
+    assert_eq!(chuckfmt(&["--jobs", "none"]).status.code(), Some(2));
+
+    // a file that can't be read is a formatting failure, not a usage error
+    let dir = scratch_dir("exit-codes");
+    let missing = dir.join("missing.ck");
+    assert_eq!(
+        chuckfmt(&[missing.to_str().unwrap()]).status.code(),
+        Some(1)
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn watch_formats_a_file_when_it_changes() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+    use std::time::{Duration, Instant};
+
+    let dir = scratch_dir("watch");
+    let file = dir.join("live.ck");
+    fs::write(&file, "SinOsc s => dac;\n").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+        .args(["--no-clang-format", "--watch"])
+        .arg(&dir)
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut line = String::new();
+    stderr.read_line(&mut line).unwrap();
+    assert!(line.contains("watching 1 file(s)"), "{line}");
+
+    fs::write(&file, "SinOsc s = > dac;\n1 ::second = > now;\n").unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let formatted = "SinOsc s => dac;\n1::second => now;\n";
+    while fs::read_to_string(&file).unwrap() != formatted && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(fs::read_to_string(&file).unwrap(), formatted);
+
+    // Ctrl-C stops watching cleanly
+    let sent = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(sent.success());
+    let status = child.wait().unwrap();
+    assert_eq!(status.code(), Some(0));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn watch_needs_something_to_watch() {
+    assert_eq!(chuckfmt(&["--watch"]).status.code(), Some(2));
+    assert_eq!(
+        chuckfmt(&["--watch", "--check", "."]).status.code(),
+        Some(2)
+    );
+}
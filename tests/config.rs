@@ -0,0 +1,50 @@
+//! Checks of `.chuckfmt.toml` settings taking effect in `process_string`.
+
+use chuckfmt::{Config, process_string};
+
+fn format_with(config: &str, input: &str) -> String {
+    let config = Config::parse(config).expect("config parses");
+    process_string(None, &[], None, &config, input)
+        .expect("formatting succeeds")
+        .output
+}
+
+#[test]
+fn disabled_print_transform_leaves_print_brackets_alone() {
+    let input = "<<<x, y>>>;\ns = > dac;\n";
+    assert_eq!(
+        format_with("[transforms]\nprint_brackets = false\n", input),
+        "<<<x, y>>>;\ns => dac;\n"
+    );
+    assert_eq!(
+        format_with("[transforms]\nprint_brackets = true\n", input),
+        "<<< x, y >>>;\ns => dac;\n"
+    );
+}
+
+#[test]
+fn custom_transforms_rewrite_the_output() {
+    let config = "[[custom_transform]]\npattern = '\\bMY_GAIN\\b'\nreplacement = \"0.5\"\n\n\
+                  [[custom_transform]]\npattern = '(\\w+)\\.gain\\((\\w+)\\)'\n\
+                  replacement = \"$2 => $1.gain\"\n";
+    // in order, after the built-in transforms
+    assert_eq!(
+        format_with(config, "s.gain(g);\nMY_GAIN = > t.gain;\n"),
+        "g => s.gain;\n0.5 => t.gain;\n"
+    );
+}
+
+#[test]
+fn invalid_custom_transform_pattern_is_an_error() {
+    let err =
+        Config::parse("[[custom_transform]]\npattern = 'a(b'\nreplacement = \"c\"\n").unwrap_err();
+    assert!(
+        err.starts_with("line 2: invalid custom_transform pattern: "),
+        "{err}"
+    );
+    let err = Config::parse("[[custom_transform]]\npattern = 'ab'\n").unwrap_err();
+    assert_eq!(
+        err,
+        "line 2: custom_transform needs both `pattern` and `replacement`"
+    );
+}
@@ -0,0 +1,44 @@
+//! Checks of `chuckfmt::diagnostics` on output clang-format could have misread.
+
+use chuckfmt::diagnostics::{Diagnostic, check};
+
+fn at(line: usize, message: &str) -> Diagnostic {
+    Diagnostic {
+        line,
+        message: message.to_string(),
+    }
+}
+
+#[test]
+fn unbalanced_print_brackets_are_flagged_on_their_line() {
+    let output = "SinOsc s => dac;\n<<< s.freq(), \"hz\";\n1::second => now;\nx >>>;\n";
+    assert_eq!(
+        check(output),
+        [
+            at(2, "`<<<` is never closed by a `>>>`"),
+            at(4, "`>>>` without a `<<<` before it"),
+        ]
+    );
+    // an opened print running to the end of the file
+    assert_eq!(check("<<< x"), [at(1, "`<<<` is never closed by a `>>>`")]);
+}
+
+#[test]
+fn chains_cut_by_a_statement_boundary_are_flagged() {
+    let output = "s =>\n    ;\n=> dac;\nfoo(x =>);\n";
+    assert_eq!(
+        check(output),
+        [
+            at(1, "`=>` with nothing to chuck to"),
+            at(3, "`=>` with nothing to chuck from"),
+            at(4, "`=>` with nothing to chuck to"),
+        ]
+    );
+}
+
+#[test]
+fn well_formed_code_and_text_in_strings_and_comments_pass() {
+    let output = "/* <<< */\nSinOsc s => dac; // => ;\n<<< \"a >>>\", s.gain() >>>;\n\
+                  1::second\n    => now;\nx @=> y;\n";
+    assert_eq!(check(output), []);
+}
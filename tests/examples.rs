@@ -0,0 +1,106 @@
+//! Golden-file checks on ChucK programs under `tests/fixtures/examples/`, adapted from the
+//! examples shipped with ChucK. Each `<name>.ck` is laid out the way people write ChucK, with
+//! the odd sloppy spot, and `<name>.expected.ck` is what chuckfmt makes of it.
+//!
+//! The ChucK transforms are always checked; the full pipeline (`--style=chuck`) is checked too
+//! when clang-format can be found, and must agree with them. After an intended change in the
+//! output, run with `CHUCKFMT_BLESS=1` to rewrite the expected files, then review their diff.
+
+use chuckfmt::{Config, expand_style_alias, process_string, resolve_clang_format};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Each example input with the path of its expected output.
+fn examples() -> Vec<(PathBuf, PathBuf)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/examples");
+    let mut inputs: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("fixture directory")
+        .map(|e| e.expect("fixture entry").path())
+        .filter(|p| {
+            p.extension().is_some_and(|e| e == "ck")
+                && !p.to_string_lossy().ends_with(".expected.ck")
+        })
+        .collect();
+    inputs.sort();
+    assert!(!inputs.is_empty(), "no examples in {}", dir.display());
+    inputs
+        .into_iter()
+        .map(|input| {
+            let expected = input.with_extension("expected.ck");
+            (input, expected)
+        })
+        .collect()
+}
+
+fn format(clang_format: Option<&Path>, opts: &[String], input: &str) -> String {
+    process_string(clang_format, opts, None, &Config::default(), input)
+        .expect("formatting succeeds")
+        .output
+}
+
+fn chuck_style() -> Vec<String> {
+    let mut opts = vec![
+        "--style=chuck".to_string(),
+        "--assume-filename=code.java".to_string(),
+    ];
+    expand_style_alias(&mut opts);
+    opts
+}
+
+fn assert_examples_match(clang_format: Option<&Path>) {
+    let bless = clang_format.is_none() && std::env::var_os("CHUCKFMT_BLESS").is_some();
+    let opts = chuck_style();
+    for (input_path, expected_path) in examples() {
+        let input = fs::read_to_string(&input_path).expect("readable example");
+        let formatted = format(clang_format, &opts, &input);
+        if bless {
+            fs::write(&expected_path, &formatted).expect("writable expected file");
+            continue;
+        }
+        let expected = fs::read_to_string(&expected_path).unwrap_or_else(|e| {
+            panic!(
+                "{}: {e} (run with CHUCKFMT_BLESS=1 to create it)",
+                expected_path.display()
+            )
+        });
+        assert_eq!(
+            formatted,
+            expected,
+            "{} doesn't format to {}",
+            input_path.display(),
+            expected_path.display()
+        );
+    }
+}
+
+#[test]
+fn examples_match_golden_output() {
+    assert_examples_match(None);
+}
+
+#[test]
+fn examples_match_golden_output_through_clang_format() {
+    let Ok(clang_format) = resolve_clang_format(None) else {
+        eprintln!("clang-format not found; skipping");
+        return;
+    };
+    assert_examples_match(Some(&clang_format));
+}
+
+#[test]
+fn golden_output_is_kept_by_clang_format() {
+    let Ok(clang_format) = resolve_clang_format(None) else {
+        eprintln!("clang-format not found; skipping");
+        return;
+    };
+    let opts = chuck_style();
+    for (_, expected_path) in examples() {
+        let expected = fs::read_to_string(&expected_path).expect("readable expected file");
+        assert_eq!(
+            format(Some(&clang_format), &opts, &expected),
+            expected,
+            "clang-format with --style=chuck changes {}",
+            expected_path.display()
+        );
+    }
+}
@@ -0,0 +1,9 @@
+// a chain longer than the 100-column limit, as clang-format wraps it (ChucK seen as Java)
+fun void voice() {
+    SinOsc osc = > LPF filter = > ADSR envelope = > NRev reverb = > Gain master = >
+            Pan2 panner = > dac;
+    osc = > Gain tap =
+        > blackhole;
+}
+SinOsc a = > Gain b = > JCRev c = > Echo d = > Chorus e = > Delay f = > LPF g = > HPF h = >
+    dac;
@@ -0,0 +1,19 @@
+// the compound ChucK operators, as clang-format splits them (ChucK seen as Java)
+0 => int x;
+1 +=> x;
+2 -=> x;
+3 *=> x;
+4 /=> x;
+5 %=> x;
+6 &=> x;
+7 |=> x;
+8 ^=> x;
+1 >>=> x;
+1 <<=> x;
+// already joined, but unspaced
+x +=> x;
+(x * 2) -=> x;
+arr[0] *=> x;
+// real shift-assignments stay as they are
+x >>= 1;
+x <<= 2;
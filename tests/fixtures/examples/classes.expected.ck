@@ -0,0 +1,50 @@
+// a class hierarchy with a static counter and an overridden method
+// (after examples/class/polymorph.ck in the ChucK distribution)
+
+class Instrument {
+    // how many instruments have been made
+    static int count;
+    Gain out;
+
+    fun void play(float pitch) {
+        <<< "instrument can't play", pitch >>>;
+    }
+
+    fun void connect(UGen u) {
+        out => u;
+        count++;
+    }
+}
+
+class Pluck extends Instrument {
+    StifKarp k => out;
+
+    fun void play(float pitch) {
+        Std.mtof(pitch) => k.freq;
+        1 => k.pluck;
+    }
+}
+
+class Blow extends Instrument {
+    Flute f => out;
+
+    fun void play(float pitch) {
+        Std.mtof(pitch) => f.freq;
+        0.8 => f.noteOn;
+    }
+}
+
+// play a melody on each instrument in turn
+Instrument @ band[2];
+new Pluck @=> band[0];
+new Blow @=> band[1];
+for (0 => int i; i < band.size(); i++) {
+    band[i].connect(dac);
+}
+
+[60, 62, 64, 67] @=> int melody[];
+for (0 => int i; i < melody.size(); i++) {
+    band[i % band.size()].play(melody[i]);
+    300::ms => now;
+}
+<<< "instruments:", Instrument.count >>>;
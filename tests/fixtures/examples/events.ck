@@ -0,0 +1,37 @@
+// broadcasting an event to several waiting shreds
+// (after examples/event/broadcast.ck in the ChucK distribution)
+
+// the event
+Event e;
+
+// a shred that waits on the event
+fun void eventshred(Event event, string msg) {
+    // infinite loop
+    while (true) {
+        // wait on the event
+        event => now;
+        // print
+        <<<msg>>>;
+    }
+}
+
+// create shreds
+spork~eventshred(e, "fee");
+spork~eventshred(e, "fi");
+spork~eventshred(e, "fo");
+spork~eventshred(e, "fum");
+
+// infinite time loop
+while (true) {
+    // either signal or broadcast
+    if (maybe) {
+        <<<"signaling...">>>;
+        e.signal();
+    } else {
+        <<<"broadcasting...">>>;
+        e.broadcast();
+    }
+
+    // advance time
+    0.5 :: second => now;
+}
@@ -0,0 +1,30 @@
+// a drum loop built from classes in other files
+@import "lib/kick.ck"
+@import "lib/hihat.ck"
+
+Kick kick => JCRev r => dac;
+Hihat hat => r;
+0.05 => r.mix;
+
+// tempo
+120 => float bpm;
+(60.0 / bpm) :: second => dur beat;
+
+// the hi-hat runs in its own shred, twice per beat
+fun void hats() {
+    while (true) {
+        hat.hit();
+        beat / 2 => now;
+    }
+}
+spork~hats();
+
+// four-on-the-floor kick, with the velocity pattern stored in an array
+[1.0, 0.6, 0.8, 0.6] @=> float accents[];
+0 => int n;
+while (true) {
+    accents[n % accents.size()] => kick.hit;
+    <<<"beat", n>>>;
+    n++;
+    beat => now;
+}
@@ -0,0 +1,23 @@
+// FM synthesis by hand: a sine modulator driving the frequency of a sine carrier
+// (after examples/basic/fm.ck in the ChucK distribution)
+
+// modulator into carrier
+SinOsc m => SinOsc c => dac;
+// let the carrier take its frequency from its input
+2 => c.sync;
+
+// carrier frequency
+440 => c.freq;
+// modulator frequency and index
+110 => m.freq;
+300 => m.gain;
+
+// sweep the modulator up, one step every 10 ms
+while (true) {
+    m.freq() * 1.01 => m.freq;
+    if (m.freq() > 1000) {
+        110 => m.freq;
+    }
+    <<< "modulator:", m.freq() >>>;
+    10::ms => now;
+}
@@ -0,0 +1,9 @@
+@import { "x.ck", "y.ck" }
+@import {
+    "a.ck",
+      "b.ck"
+} // deps
+@import
+    "z.ck"
+@import "w.ck"
+SinOsc s => dac;
@@ -0,0 +1,15 @@
+// ChucK operators in their usual spellings
+SinOsc s = > Gain g = > dac;
+s = < dac;
+a = < b = < c;
+osc =< lpf =< dac;
+[1, 2, 3] @ => int arr[];
+1 += > x;
+2 -= >x;
+y >> => z;
+a = ^ b;
+x --> y;
+x-- < y;
+if (i-- > 3) i--> j;
+2 *b => c;
+%( 1, .5 ) => polar p;
@@ -0,0 +1,8 @@
+// debug prints
+<<<x>>>;
+<<< "a, b" ,foo(a ,b) , [1 ,2]>>>;
+< < < "hi" >>>;
+<<< a,
+    b >>>;
+chout<=x<=IO.nl();
+cherr <= "err" <= IO.newline();
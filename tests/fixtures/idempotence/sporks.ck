@@ -0,0 +1,12 @@
+fun void go(int n) {
+    - 3.14 => float v;
+    + x => float w;
+    while (true) 1::second => now;
+}
+spork ~go(1);
+spork~ go(2);
+spork
+    ~ me.yield();
+"a => b" => string s; // comment with a = > b
+/* block = > comment */ 3 => int k;
+@import "lib.ck"
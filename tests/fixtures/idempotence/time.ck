@@ -0,0 +1,8 @@
+// durations and time advance
+1 ::second => now;
+x :: ms => now;
+(n + 1) :: samp => now;
+samp => now;
+(d) => now;
+.5::second +=> t;
+Std.mtof(60) => float f;
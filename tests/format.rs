@@ -0,0 +1,549 @@
+//! End-to-end checks of `process_string` without clang-format (pre-transforms, then the ChucK
+//! transforms).
+
+use chuckfmt::{
+    Config, apply_pre_formatting_transforms, apply_transforms, normalize_unicode_operators,
+    process_string,
+};
+
+fn format(input: &str) -> String {
+    process_string(None, &[], None, &Config::default(), input)
+        .expect("formatting succeeds")
+        .output
+}
+
+#[test]
+fn braced_import_is_kept_as_written() {
+    let input = "@import { \"x.ck\", \"y.ck\" }\nSinOsc s => dac;\n";
+    assert_eq!(format(input), input);
+}
+
+#[test]
+fn import_semicolon_goes_before_a_trailing_comment() {
+    let input = "@import \"x.ck\" // note\n@import \"y.ck\" /* \"z\"; */\nSinOsc s = > dac;\n";
+    let pre = apply_pre_formatting_transforms(input);
+    assert_eq!(
+        pre,
+        "@import \"x.ck\"; // note\n@import \"y.ck\"; /* \"z\"; */\nSinOsc s = > dac;\n"
+    );
+    assert_eq!(
+        apply_transforms(&pre),
+        "@import \"x.ck\" // note\n@import \"y.ck\" /* \"z\"; */\nSinOsc s => dac;\n"
+    );
+    // a `;` ending the comment is the comment's own
+    let input = "@import \"x.ck\" // note;\nSinOsc s => dac;\n";
+    assert_eq!(format(input), input);
+}
+
+#[test]
+fn operators_inside_string_literals_are_left_alone() {
+    assert_eq!(
+        format("chout <= \"press = > to continue, a<=b\" <= IO.nl();\n"),
+        "chout <= \"press = > to continue, a<=b\" <= IO.nl();\n"
+    );
+    // an escaped quote doesn't end the string
+    assert_eq!(
+        format("chout<=\"say \\\"x = > y\\\" <<<z>>>\"<=IO.nl();\n"),
+        "chout <= \"say \\\"x = > y\\\" <<<z>>>\" <= IO.nl();\n"
+    );
+}
+
+#[test]
+fn comment_bodies_are_left_alone() {
+    assert_eq!(
+        format("s = > dac; // a = > b <<<x>>>\n/* c = > d;\n   1 ::second = > now; */\n"),
+        "s => dac; // a = > b <<<x>>>\n/* c = > d;\n   1 ::second = > now; */\n"
+    );
+    // and so does a comment in the middle of a statement
+    assert_eq!(format("a /* = > */ => b;\n"), "a /* = > */ => b;\n");
+}
+
+#[test]
+fn multi_line_imports_are_kept_as_written() {
+    let input =
+        "@import {\n    \"a.ck\",\n      \"b.ck\"\n}\n@import\n    \"z.ck\"\n@import \"w.ck\"\n";
+    assert_eq!(format(input), input);
+}
+
+#[test]
+fn byte_order_mark_is_kept_without_hiding_the_first_line() {
+    let input = "\u{FEFF}@import \"lib.ck\"\nSinOsc s = > dac;\n";
+    assert_eq!(
+        format(input),
+        "\u{FEFF}@import \"lib.ck\"\nSinOsc s => dac;\n"
+    );
+    assert_eq!(format("s = > dac;\n"), "s => dac;\n");
+}
+
+#[test]
+fn comment_and_import_only_input_skips_clang_format() {
+    // a clang-format that doesn't exist would fail the moment it was spawned
+    let missing = std::path::Path::new("/nonexistent/clang-format");
+    for input in [
+        "// just a note\n//    with   odd spacing, kept as written\n",
+        "/* a block\n   comment */\n\n// and a line one\n",
+        "@import \"lib.ck\"\n@import \"util.ck\" // helpers\n",
+    ] {
+        let formatted = process_string(Some(missing), &[], None, &Config::default(), input);
+        assert_eq!(formatted.unwrap().output, input);
+    }
+}
+
+#[test]
+fn blank_input_is_returned_without_running_clang_format() {
+    // a clang-format that doesn't exist would fail the moment it was spawned
+    let missing = std::path::Path::new("/nonexistent/clang-format");
+    for input in ["", "\n", "  \r\n\t\n", "\u{FEFF}"] {
+        let formatted = process_string(Some(missing), &[], None, &Config::default(), input);
+        assert_eq!(formatted.unwrap().output, input);
+    }
+}
+
+#[test]
+fn skip_marked_input_is_returned_byte_for_byte() {
+    let missing = std::path::Path::new("/nonexistent/clang-format");
+    for input in [
+        "// chuckfmt: skip\nSinOsc s = > dac;\r\n<<<x>>>;",
+        "\u{FEFF}\n  // chuckfmt-ignore\n1 ::second = > now;\n",
+    ] {
+        let formatted = process_string(Some(missing), &[], None, &Config::default(), input);
+        assert_eq!(formatted.unwrap().output, input);
+    }
+    // only on the first non-blank line
+    assert_eq!(
+        format("s = > dac;\n// chuckfmt: skip\n"),
+        "s => dac;\n// chuckfmt: skip\n"
+    );
+}
+
+#[test]
+fn unicode_arrows_become_chuck_operators_outside_strings_and_comments() {
+    let input = "SinOsc s ⇒ dac; // s → dac\nif (x ≤ 1 && y≠2) <<< \"a ⇒ b\" >>>;\n";
+    let normalized = normalize_unicode_operators(input);
+    assert_eq!(
+        normalized,
+        "SinOsc s => dac; // s → dac\nif (x <= 1 && y!=2) <<< \"a ⇒ b\" >>>;\n"
+    );
+    assert_eq!(
+        format(&normalized),
+        "SinOsc s => dac; // s → dac\nif (x <= 1 && y!=2) <<< \"a ⇒ b\" >>>;\n"
+    );
+    // `←` has no single ChucK reading and is left alone
+    assert_eq!(normalize_unicode_operators("a ← b;"), "a ← b;");
+}
+
+#[cfg(unix)]
+#[test]
+fn hanging_clang_format_times_out() {
+    use chuckfmt::FmtError;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Duration;
+
+    let script = std::env::temp_dir().join(format!("chuckfmt-hang-{}", std::process::id()));
+    std::fs::write(&script, "#!/bin/sh\nexec sleep 5\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let timeout = Some(Duration::from_millis(200));
+    let result = process_string(Some(&script), &[], timeout, &Config::default(), "a => b;\n");
+    let _ = std::fs::remove_file(&script);
+    assert!(matches!(result, Err(FmtError::ClangFormatTimedOut(_))));
+}
+
+#[cfg(unix)]
+#[test]
+fn megabytes_through_clang_format_do_not_fill_the_pipes() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // echoes its input on both stdout and stderr, so neither pipe is drained by waiting
+    let script = std::env::temp_dir().join(format!("chuckfmt-echo-{}", std::process::id()));
+    std::fs::write(&script, "#!/bin/sh\ntee /dev/stderr\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    // about 3.4 MB, far past any pipe buffer
+    let input = "SinOsc s => dac;\n".repeat(200_000);
+    let formatted = process_string(Some(&script), &[], None, &Config::default(), &input);
+    let _ = std::fs::remove_file(&script);
+    let formatted = formatted.unwrap();
+    assert_eq!(formatted.output, input);
+    assert_eq!(formatted.stderr, input);
+}
+
+#[cfg(unix)]
+#[test]
+fn failing_clang_format_reports_its_stderr() {
+    use chuckfmt::FmtError;
+    use std::os::unix::fs::PermissionsExt;
+
+    let script = std::env::temp_dir().join(format!("chuckfmt-fail-{}", std::process::id()));
+    std::fs::write(
+        &script,
+        "#!/bin/sh\ncat >/dev/null\necho 'error: unknown key BasedOnStyle' >&2\nexit 3\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let result = process_string(Some(&script), &[], None, &Config::default(), "a => b;\n");
+    let _ = std::fs::remove_file(&script);
+    let err = result.unwrap_err();
+    match &err {
+        FmtError::ClangFormatFailed { code, stderr } => {
+            assert_eq!(*code, Some(3));
+            assert_eq!(stderr, "error: unknown key BasedOnStyle\n");
+        }
+        other => panic!("expected ClangFormatFailed, got {other:?}"),
+    }
+    assert_eq!(
+        err.to_string(),
+        "clang-format failed with exit code Some(3): error: unknown key BasedOnStyle"
+    );
+}
+
+#[test]
+fn cursor_follows_a_collapsed_operator() {
+    // cursor right after the `>` of `= >`
+    let opts = ["-cursor=5".to_string()];
+    let formatted = process_string(None, &opts, None, &Config::default(), "x = > y;\n").unwrap();
+    assert_eq!(formatted.cursor, Some(4));
+    assert_eq!(
+        formatted.output,
+        "{ \"Cursor\": 4, \"IncompleteFormat\": false }\nx => y;\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn cursor_reported_by_clang_format_is_mapped_through_the_transforms() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // echoes the cursor it is given, like clang-format on already formatted code
+    let script = std::env::temp_dir().join(format!("chuckfmt-cursor-{}", std::process::id()));
+    std::fs::write(
+        &script,
+        "#!/bin/sh\necho \"{ \\\"Cursor\\\": ${1#-cursor=}, \\\"IncompleteFormat\\\": false }\"\ncat\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    // cursor on the `b` after `-->`, which clang-format sees as ` instanceof `
+    let opts = ["-cursor".to_string(), "13".to_string()];
+    let input = "x = > y;\na-->b;\n";
+    let formatted = process_string(Some(&script), &opts, None, &Config::default(), input);
+    let _ = std::fs::remove_file(&script);
+    let formatted = formatted.unwrap();
+    assert_eq!(formatted.cursor, Some(14));
+    assert!(formatted.output.ends_with("}\nx => y;\na --> b;\n"));
+}
+
+#[test]
+fn names_spelled_instanceof_are_not_taken_for_the_arrow() {
+    assert_eq!(
+        format("int instanceof;\ninstanceof_ --> instanceof;\na-->b;\n"),
+        "int instanceof;\ninstanceof_ --> instanceof;\na --> b;\n"
+    );
+    // the stand-ins clang-format sees are told apart too
+    let pre = apply_pre_formatting_transforms("int instanceof;\nx-->instanceof_;\n");
+    assert_eq!(
+        apply_transforms(&pre),
+        "int instanceof;\nx --> instanceof_;\n"
+    );
+}
+
+#[test]
+fn line_range_leaves_other_lines_untouched() {
+    let input = "a = > b;\nc = > d;\ne = > f;\ng = > h;\ni = >  j;\n";
+    let opts = ["-lines=2:3".to_string()];
+    let formatted = process_string(None, &opts, None, &Config::default(), input).unwrap();
+    assert_eq!(
+        formatted.output,
+        "a = > b;\nc => d;\ne => f;\ng = > h;\ni = >  j;\n"
+    );
+    assert_eq!(formatted.output.lines().nth(4), input.lines().nth(4));
+}
+
+#[test]
+fn compound_operators_match_golden_output() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/compound");
+    let input = std::fs::read_to_string(dir.join("operators.ck")).unwrap();
+    let expected = std::fs::read_to_string(dir.join("operators.expected.ck")).unwrap();
+    assert_eq!(format(&input), expected);
+    assert_eq!(format(&expected), expected);
+}
+
+#[test]
+fn wrapped_chains_match_golden_output() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/chains");
+    let input = std::fs::read_to_string(dir.join("wrapped.ck")).unwrap();
+    let expected = std::fs::read_to_string(dir.join("wrapped.expected.ck")).unwrap();
+    // by default clang-format's continuation indent is kept
+    let kept = "Gain master =>\n            Pan2 panner => dac;\n";
+    assert!(format(&input).contains(kept), "{}", format(&input));
+
+    let mut config = Config::default();
+    config.chain.reflow = true;
+    let format = |input: &str| {
+        process_string(None, &[], None, &config, input)
+            .unwrap()
+            .output
+    };
+    assert_eq!(format(&input), expected);
+    assert_eq!(format(&expected), expected);
+
+    config.chain.break_before = true;
+    let formatted = process_string(None, &[], None, &config, &input).unwrap();
+    let chain = concat!(
+        "    SinOsc osc => LPF filter => ADSR envelope => NRev reverb => Gain master\n",
+        "        => Pan2 panner => dac;\n",
+    );
+    assert!(formatted.output.contains(chain), "{}", formatted.output);
+}
+
+#[test]
+fn chuckfmt_off_region_is_kept_as_written() {
+    let input = concat!(
+        "SinOsc s = > dac;\n",
+        "// chuckfmt: off\n",
+        "SinOsc a   = >  Gain g = > dac;\n",
+        "TriOsc bb  = >  g;\n",
+        "x-->y;\n",
+        "// chuckfmt: on\n",
+        "1 ::second = > now;\n",
+    );
+    let pre = chuckfmt::apply_pre_formatting_transforms(input);
+    assert!(
+        pre.contains("// chuckfmt: off\n// clang-format off\n"),
+        "{pre}"
+    );
+    assert!(
+        pre.contains("// clang-format on\n// chuckfmt: on\n"),
+        "{pre}"
+    );
+    assert!(pre.contains("x-->y;"), "{pre}");
+
+    assert_eq!(
+        format(input),
+        input.replacen("s = > dac", "s => dac", 1).replacen(
+            "1 ::second = > now",
+            "1::second => now",
+            1
+        )
+    );
+
+    // a region left open runs to the end
+    let open = "a = > b;\n// chuckfmt: off\nc = > d;\n";
+    assert_eq!(format(open), "a => b;\n// chuckfmt: off\nc = > d;\n");
+}
+
+#[test]
+fn column_limit_is_merged_into_the_style() {
+    let limited = |opts: &[&str]| {
+        let mut opts: Vec<String> = opts.iter().map(|o| o.to_string()).collect();
+        chuckfmt::apply_column_limit(&mut opts, 40).unwrap();
+        opts
+    };
+    assert_eq!(
+        limited(&[]),
+        ["--style={BasedOnStyle: InheritParentConfig, ColumnLimit: 40}"]
+    );
+    assert_eq!(
+        limited(&["-style=LLVM"]),
+        ["-style={BasedOnStyle: LLVM, ColumnLimit: 40}"]
+    );
+    assert_eq!(
+        limited(&["--style", "{IndentWidth: 4, ColumnLimit: 100}"]),
+        ["--style", "{IndentWidth: 4, ColumnLimit: 40}"]
+    );
+
+    let mut chuck = vec!["--style=chuck".to_string()];
+    chuckfmt::expand_style_alias(&mut chuck);
+    chuckfmt::apply_column_limit(&mut chuck, 40).unwrap();
+    assert!(chuck[0].ends_with(", ColumnLimit: 40}"), "{}", chuck[0]);
+    assert!(!chuck[0].contains("ColumnLimit: 100"));
+}
+
+#[test]
+fn short_column_limit_wraps_a_long_statement() {
+    let Ok(clang_format) = chuckfmt::resolve_clang_format(None) else {
+        eprintln!("clang-format not found; skipping");
+        return;
+    };
+    let mut opts = vec![
+        "--style=chuck".to_string(),
+        "--assume-filename=code.java".to_string(),
+    ];
+    chuckfmt::expand_style_alias(&mut opts);
+    chuckfmt::apply_column_limit(&mut opts, 30).unwrap();
+    let input = "foo(first_argument, second_argument, third_argument);\n";
+    let formatted =
+        process_string(Some(&clang_format), &opts, None, &Config::default(), input).unwrap();
+    assert!(formatted.output.lines().count() > 1, "{}", formatted.output);
+    assert!(formatted.output.lines().all(|l| l.len() <= 30));
+}
+
+#[test]
+fn max_empty_lines_is_merged_into_the_style() {
+    let mut opts = vec!["--style=chuck".to_string()];
+    chuckfmt::expand_style_alias(&mut opts);
+    chuckfmt::apply_column_limit(&mut opts, 80).unwrap();
+    chuckfmt::apply_max_empty_lines(&mut opts, 2).unwrap();
+    assert_eq!(opts.len(), 1);
+    assert!(
+        opts[0].ends_with(", ColumnLimit: 80, MaxEmptyLinesToKeep: 2}"),
+        "{}",
+        opts[0]
+    );
+
+    let mut opts = Vec::new();
+    chuckfmt::apply_max_empty_lines(&mut opts, 2).unwrap();
+    assert_eq!(
+        opts,
+        ["--style={BasedOnStyle: InheritParentConfig, MaxEmptyLinesToKeep: 2}"]
+    );
+}
+
+#[test]
+fn keep_two_blank_lines_between_definitions() {
+    let Ok(clang_format) = chuckfmt::resolve_clang_format(None) else {
+        eprintln!("clang-format not found; skipping");
+        return;
+    };
+    let mut opts = vec![
+        "--style=chuck".to_string(),
+        "--assume-filename=code.java".to_string(),
+    ];
+    chuckfmt::expand_style_alias(&mut opts);
+    chuckfmt::apply_max_empty_lines(&mut opts, 2).unwrap();
+    let input = "fun void a() {\n    1::second => now;\n}\n\n\n\nfun void b() {\n    2::second => now;\n}\n";
+    let formatted =
+        process_string(Some(&clang_format), &opts, None, &Config::default(), input).unwrap();
+    assert!(
+        formatted.output.contains("}\n\n\nfun void b()"),
+        "{}",
+        formatted.output
+    );
+    assert!(!formatted.output.contains("\n\n\n\n"));
+}
+
+#[cfg(unix)]
+#[test]
+fn clang_format_dropping_code_is_an_error() {
+    use chuckfmt::FmtError;
+    use std::os::unix::fs::PermissionsExt;
+
+    // a clang-format that loses the closing print brackets
+    let script = std::env::temp_dir().join(format!("chuckfmt-drop-{}", std::process::id()));
+    std::fs::write(&script, "#!/bin/sh\nsed 's/>>>//'\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let input = "<<< \"a => b\", x >>>; // >>> in a comment is fine\n";
+    let result = process_string(Some(&script), &[], None, &Config::default(), input);
+    let _ = std::fs::remove_file(&script);
+    match result {
+        Err(FmtError::ClangFormatChangedCode { lost, gained }) => {
+            assert_eq!(lost, [">", ">", ">"]);
+            assert!(gained.is_empty());
+        }
+        other => panic!("expected ClangFormatChangedCode, got {other:?}"),
+    }
+}
+
+#[test]
+fn chuck_assume_filenames_get_a_java_extension() {
+    let mut opts: Vec<String> = [
+        "--assume-filename=foo.ck",
+        "-assume-filename",
+        "src/bar.chuck",
+        "--assume-filename=x.cpp",
+        "foo.ck",
+    ]
+    .map(String::from)
+    .to_vec();
+    chuckfmt::complete_assume_filename(&mut opts, "java");
+    assert_eq!(
+        opts,
+        [
+            "--assume-filename=foo.ck.java",
+            "-assume-filename",
+            "src/bar.chuck.java",
+            "--assume-filename=x.cpp",
+            "foo.ck",
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn time_advance_idiom_survives_split_operators() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // a clang-format that splits `=>` and `::` apart, as its Java layout does
+    let script = std::env::temp_dir().join(format!("chuckfmt-split-{}", std::process::id()));
+    std::fs::write(
+        &script,
+        "#!/bin/sh\nsed 's/ *=> */ = > /g; s/ *:: */ :: /g'\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let input = "1::second=>now;\nsamp=>now;\n(dur)=>now;\n(n + 1)::ms => now;\n";
+    let formatted = process_string(Some(&script), &[], None, &Config::default(), input);
+    let _ = std::fs::remove_file(&script);
+    assert_eq!(
+        formatted.unwrap().output,
+        "1::second => now;\nsamp => now;\n(dur) => now;\n(n + 1)::ms => now;\n"
+    );
+}
+
+#[test]
+fn trailing_newline_matches_the_input() {
+    assert_eq!(format("a = > b;\n"), "a => b;\n");
+    assert_eq!(format("a = > b;"), "a => b;");
+}
+
+#[cfg(unix)]
+#[test]
+fn trailing_newline_added_by_clang_format_follows_the_input() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // a clang-format that ends its output with a newline, as the real one does
+    let script = std::env::temp_dir().join(format!("chuckfmt-newline-{}", std::process::id()));
+    std::fs::write(&script, "#!/bin/sh\nawk 1\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let format = |input: &str| {
+        process_string(Some(&script), &[], None, &Config::default(), input)
+            .unwrap()
+            .output
+    };
+    let with = format("a = > b;\n");
+    let without = format("a = > b;");
+    let _ = std::fs::remove_file(&script);
+    assert_eq!(with, "a => b;\n");
+    assert_eq!(without, "a => b;");
+}
+
+#[test]
+fn crlf_line_endings_are_kept() {
+    assert_eq!(
+        format("a = > b;\r\n<<<x>>>;\r\nc => d;\r\n"),
+        "a => b;\r\n<<< x >>>;\r\nc => d;\r\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn crlf_line_endings_survive_clang_format() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // a clang-format that writes LF line endings whatever it was given
+    let script = std::env::temp_dir().join(format!("chuckfmt-lf-{}", std::process::id()));
+    std::fs::write(&script, "#!/bin/sh\ntr -d '\\r'\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let input = "SinOsc s = > dac;\r\n1 :: second => now;\r\n<<<\"done\">>>;\r\n";
+    let formatted = process_string(Some(&script), &[], None, &Config::default(), input);
+    let _ = std::fs::remove_file(&script);
+    assert_eq!(
+        formatted.unwrap().output,
+        "SinOsc s => dac;\r\n1::second => now;\r\n<<< \"done\" >>>;\r\n"
+    );
+}
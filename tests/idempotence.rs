@@ -0,0 +1,56 @@
+//! Formatting must be a fixed point: `format(format(x)) == format(x)` for every fixture under
+//! `tests/fixtures/idempotence/`.
+//!
+//! The ChucK transforms are always checked; the full pipeline is checked too when clang-format
+//! can be found.
+
+use chuckfmt::{Config, process_string, resolve_clang_format};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn fixtures() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/idempotence");
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("fixture directory")
+        .map(|e| e.expect("fixture entry").path())
+        .filter(|p| p.extension().is_some_and(|e| e == "ck"))
+        .collect();
+    files.sort();
+    assert!(!files.is_empty(), "no fixtures in {}", dir.display());
+    files
+}
+
+fn assert_idempotent(clang_format: Option<&Path>) {
+    let opts = vec!["--assume-filename=code.java".to_string()];
+    let format = |input: &str| {
+        process_string(clang_format, &opts, None, &Config::default(), input)
+            .expect("formatting succeeds")
+            .output
+    };
+
+    for path in fixtures() {
+        let input = fs::read_to_string(&path).expect("readable fixture");
+        let once = format(&input);
+        let twice = format(&once);
+        assert_eq!(
+            once,
+            twice,
+            "{} is not a fixed point of formatting",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn transforms_are_idempotent() {
+    assert_idempotent(None);
+}
+
+#[test]
+fn clang_format_pipeline_is_idempotent() {
+    let Ok(clang_format) = resolve_clang_format(None) else {
+        eprintln!("clang-format not found; skipping");
+        return;
+    };
+    assert_idempotent(Some(&clang_format));
+}
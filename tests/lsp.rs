@@ -0,0 +1,102 @@
+//! `--lsp`: drives the language server over stdio like an editor would.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn message(body: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{body}", body.len())
+}
+
+/// Runs `chuckfmt --lsp` with `args` on `requests`, bracketed by `initialize` and
+/// `shutdown`/`exit`, returning everything it wrote.
+fn session(args: &[&str], requests: &[String]) -> String {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+        .args(args)
+        .arg("--lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("chuckfmt runs");
+
+    let mut all = vec![
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#
+            .to_string(),
+        r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#.to_string(),
+    ];
+    all.extend_from_slice(requests);
+    all.push(r#"{"jsonrpc":"2.0","id":99,"method":"shutdown"}"#.to_string());
+    all.push(r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string());
+    let input: String = all.iter().map(|r| message(r)).collect();
+    server
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    let out = server.wait_with_output().unwrap();
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(
+        stdout.contains(r#"{"jsonrpc":"2.0","id":99,"result":null}"#),
+        "{stdout}"
+    );
+    stdout
+}
+
+#[test]
+fn formatting_request_returns_text_edits() {
+    let uri = "file:///tmp/chuckfmt-lsp-test.ck";
+    let stdout = session(
+        &["--no-clang-format"],
+        &[
+            format!(
+                r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"{uri}","languageId":"chuck","version":1,"text":"SinOsc s;\ns = > dac;\n"}}}}}}"#
+            ),
+            format!(
+                r#"{{"jsonrpc":"2.0","id":2,"method":"textDocument/formatting","params":{{"textDocument":{{"uri":"{uri}"}},"options":{{"tabSize":4,"insertSpaces":true}}}}}}"#
+            ),
+        ],
+    );
+    assert!(
+        stdout.contains(r#""documentFormattingProvider":true"#),
+        "{stdout}"
+    );
+    let edits = r#"{"jsonrpc":"2.0","id":2,"result":[{"range":{"start":{"line":1,"character":3},"end":{"line":1,"character":4}},"newText":""}]}"#;
+    assert!(stdout.contains(&message(edits)), "{stdout}");
+}
+
+#[cfg(unix)]
+#[test]
+fn range_formatting_edits_only_the_range() {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    // a clang-format that squeezes runs of blank lines, in front of the range too
+    let dir = std::env::temp_dir().join(format!("chuckfmt-lsp-range-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let clang = dir.join("clang-format");
+    fs::write(
+        &clang,
+        "#!/bin/sh\n[ \"$1\" = --version ] && echo 'clang-format version 18.1.3' && exit\ncat -s\n",
+    )
+    .unwrap();
+    fs::set_permissions(&clang, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let uri = "file:///tmp/chuckfmt-lsp-range.ck";
+    let stdout = session(
+        &["--clang-format-path", clang.to_str().unwrap()],
+        &[
+            format!(
+                r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"{uri}","languageId":"chuck","version":1,"text":"a = > b;\n\n\n    c = > d;\ne = > f;\n"}}}}}}"#
+            ),
+            format!(
+                r#"{{"jsonrpc":"2.0","id":2,"method":"textDocument/rangeFormatting","params":{{"textDocument":{{"uri":"{uri}"}},"range":{{"start":{{"line":3,"character":0}},"end":{{"line":3,"character":12}}}},"options":{{"tabSize":4,"insertSpaces":true}}}}}}"#
+            ),
+        ],
+    );
+    fs::remove_dir_all(&dir).unwrap();
+    let edits = r#"{"jsonrpc":"2.0","id":2,"result":[{"range":{"start":{"line":3,"character":7},"end":{"line":3,"character":8}},"newText":""}]}"#;
+    assert!(stdout.contains(&message(edits)), "{stdout}");
+}
@@ -0,0 +1,44 @@
+//! Checks of `chuckfmt::markdown` on finding ChucK code blocks in Markdown.
+
+use chuckfmt::markdown::format_blocks;
+use std::convert::Infallible;
+
+/// `doc` with every ChucK block's code uppercased, and the lines the blocks start on.
+fn upcase_blocks(doc: &str) -> (String, Vec<usize>) {
+    let mut starts = Vec::new();
+    let out = format_blocks(doc, |code, line| {
+        starts.push(line);
+        Ok::<_, Infallible>(code.to_uppercase())
+    })
+    .unwrap();
+    (out, starts)
+}
+
+#[test]
+fn only_chuck_blocks_are_formatted() {
+    let doc = "# Title\n\nsome prose\n\n```chuck\nsinosc s;\n```\n\n```c\nint x;\n```\n\n~~~ ck {.numberLines}\nnow;\n~~~\n";
+    let (out, starts) = upcase_blocks(doc);
+    assert_eq!(
+        out,
+        "# Title\n\nsome prose\n\n```chuck\nSINOSC S;\n```\n\n```c\nint x;\n```\n\n~~~ ck {.numberLines}\nNOW;\n~~~\n"
+    );
+    assert_eq!(starts, [6, 14]);
+}
+
+#[test]
+fn indented_blocks_keep_their_indentation() {
+    let doc = "- step one:\n\n  ```chuck\n  fun void f() {\n      now;\n\n  }\n  ```\n";
+    let (out, _) = upcase_blocks(doc);
+    assert_eq!(
+        out,
+        "- step one:\n\n  ```chuck\n  FUN VOID F() {\n      NOW;\n\n  }\n  ```\n"
+    );
+}
+
+#[test]
+fn nested_and_unclosed_fences_are_left_alone() {
+    let nested = "````markdown\n```chuck\nsinosc s;\n```\n````\n";
+    assert_eq!(upcase_blocks(nested).0, nested);
+    let unclosed = "text\n```chuck\nsinosc s;\n";
+    assert_eq!(upcase_blocks(unclosed).0, unclosed);
+}
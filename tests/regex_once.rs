@@ -0,0 +1,34 @@
+//! The transforms' regexes are compiled on first use and kept for the life of the process, not
+//! compiled again on every call. This file holds a single test so that its first call really is
+//! the first in the process.
+
+use chuckfmt::apply_transforms;
+use std::time::{Duration, Instant};
+
+fn time(f: impl FnOnce()) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+#[test]
+fn regexes_are_compiled_once_per_process() {
+    let input = "SinOsc s = > dac;\n1 :: second => now;\n<<<x>>>;\n";
+    let first = time(|| {
+        apply_transforms(input);
+    });
+    // the fastest of many later calls, so a busy machine can't make them look slow
+    let later = (0..50)
+        .map(|_| {
+            time(|| {
+                apply_transforms(input);
+            })
+        })
+        .min()
+        .unwrap();
+    // compiling every regex again would make each call about as slow as the first
+    assert!(
+        later * 4 < first,
+        "first call {first:?}, later calls {later:?}"
+    );
+}
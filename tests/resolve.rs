@@ -0,0 +1,98 @@
+//! clang-format resolution, in a test binary of its own since it changes the environment.
+
+#![cfg(unix)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Fake versioned clang-formats, one per name, in a fresh directory.
+fn fake_clang_formats(test: &str, names: &[&str]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chuckfmt-{test}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for name in names {
+        let path = dir.join(name);
+        fs::write(&path, "#!/bin/sh\necho 'clang-format version 18.1.3'\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    dir
+}
+
+/// The clang-format `chuckfmt --version` reports with only `path` on PATH and `names`, if
+/// given, as `CHUCKFMT_CLANG_FORMAT_NAMES`.
+fn resolved_on(path: &Path, names: Option<&str>) -> String {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_chuckfmt"));
+    command.env_clear().env("PATH", path).arg("--version");
+    if let Some(names) = names {
+        command.env("CHUCKFMT_CLANG_FORMAT_NAMES", names);
+    }
+    let stdout = String::from_utf8(command.output().unwrap().stdout).unwrap();
+    stdout.lines().nth(1).unwrap_or_default().to_string()
+}
+
+#[test]
+fn cached_resolution_ignores_later_changes_to_clang_format_bin() {
+    let dir = std::env::temp_dir().join(format!("chuckfmt-resolve-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let [first, second] = ["first-format", "second-format"].map(|name| {
+        let path = dir.join(name);
+        fs::write(&path, "#!/bin/sh\necho 'clang-format version 18.1.3'\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    });
+
+    // SAFETY: the other tests here give chuckfmt an environment of their own rather than
+    // reading this one, so nothing reads it meanwhile
+    unsafe { std::env::set_var("CLANG_FORMAT_BIN", &first) };
+    assert_eq!(chuckfmt::resolve_clang_format_cached().unwrap(), first);
+    unsafe { std::env::set_var("CLANG_FORMAT_BIN", &second) };
+    assert_eq!(chuckfmt::resolve_clang_format_cached().unwrap(), first);
+    assert_eq!(chuckfmt::resolve_clang_format(None).unwrap(), second);
+
+    // a fresh run resolves again
+    let out = Command::new(env!("CARGO_BIN_EXE_chuckfmt"))
+        .arg("--version")
+        .env("CLANG_FORMAT_BIN", &second)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(
+        stdout.contains(&format!("clang-format: {}", second.display())),
+        "{stdout}"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn versioned_binaries_are_found_newest_first() {
+    let dir = fake_clang_formats("versioned", &["clang-format-15", "clang-format-18"]);
+    assert_eq!(
+        resolved_on(&dir, None),
+        format!(
+            "clang-format: {} (clang-format version 18.1.3)",
+            dir.join("clang-format-18").display()
+        )
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn candidate_names_can_be_overridden() {
+    let dir = fake_clang_formats(
+        "names",
+        &["clang-format-15", "clang-format-18", "my-format"],
+    );
+    let found = |names| resolved_on(&dir, Some(names));
+    assert!(
+        found("missing-format, clang-format-15")
+            .contains(&format!("{} ", dir.join("clang-format-15").display()))
+    );
+    assert!(found("my-format").contains(&format!("{} ", dir.join("my-format").display())));
+    // the usual names aren't tried once the list is replaced
+    assert_eq!(found("missing-format"), "clang-format: not found");
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,280 @@
+//! Focused checks for the individual transforms in `chuckfmt::transforms`.
+
+use chuckfmt::transforms::*;
+use chuckfmt::{Config, OperatorSpacing, Spacing, TRANSFORM_NAMES};
+
+#[test]
+fn names_match_config_names() {
+    let names: Vec<&str> = ALL.iter().map(|&(name, _)| name).collect();
+    assert_eq!(names, TRANSFORM_NAMES);
+}
+
+#[test]
+fn chuck_operators() {
+    assert_eq!(chuck_arrow("s = > dac;"), "s => dac;");
+    assert_eq!(unchuck("s = < dac;"), "s =< dac;");
+    assert_eq!(at_chuck("[1] @ => int a[];"), "[1] @=> int a[];");
+    assert_eq!(upchuck("a = ^ b;"), "a =^ b;");
+}
+
+#[test]
+fn unchuck_chains_and_lone_equals() {
+    assert_eq!(unchuck("a = < b = < c;"), "a =< b =< c;");
+    assert_eq!(unchuck("a =< b =< c;"), "a =< b =< c;");
+    assert_eq!(unchuck("a =< b =\n        < c;"), "a =< b =< c;");
+    assert_eq!(unchuck("a =< b =<\n    c;"), "a =< b =<\n    c;");
+    // no plain `=` in ChucK: a lone `=` before `<` is an unchuck
+    assert_eq!(unchuck("x = <y;"), "x =< y;");
+    assert_eq!(unchuck("x=<y;"), "x =< y;");
+    // the `=` of another operator
+    assert_eq!(unchuck("a == < b;"), "a == < b;");
+    assert_eq!(unchuck("a <= < b;"), "a <= < b;");
+    assert_eq!(unchuck("a != <b;"), "a != <b;");
+}
+
+#[test]
+fn output_operator_is_spaced_and_never_unchucked() {
+    assert_eq!(output("chout<=x<=IO.nl();"), "chout <= x <= IO.nl();");
+    assert_eq!(output("chout <=  x  <= IO.nl();"), "chout <= x <= IO.nl();");
+    assert_eq!(
+        output("cherr <= \"a\"\n    <= IO.nl();"),
+        "cherr <= \"a\"\n    <= IO.nl();"
+    );
+    assert_eq!(output("a <<= 2;"), "a <<= 2;");
+    // the whole pipeline keeps it `<=`, not the unchuck `=<`
+    assert_eq!(
+        chuckfmt::apply_transforms("chout <= x <= IO.nl();\nchout<=\"hi\";\n"),
+        "chout <= x <= IO.nl();\nchout <= \"hi\";\n"
+    );
+}
+
+#[test]
+fn compound_chuck_merges_and_spaces() {
+    assert_eq!(compound_chuck("1 + => x;"), "1 +=> x;");
+    assert_eq!(compound_chuck("1 +=>x;"), "1 +=> x;");
+    assert_eq!(compound_chuck("y >> => z;"), "y >>=> z;");
+    assert_eq!(compound_chuck("a >>= 2;"), "a >>= 2;");
+}
+
+#[test]
+fn duration_collapses_left_operands() {
+    assert_eq!(duration("1 ::second"), "1::second");
+    assert_eq!(duration("x :: ms"), "x::ms");
+    assert_eq!(duration("(n + 1) :: samp"), "(n + 1)::samp");
+    assert_eq!(duration("Foo::bar()"), "Foo::bar()");
+}
+
+#[test]
+fn duration_leaves_namespace_members_spaced() {
+    assert_eq!(duration("Foo :: bar"), "Foo :: bar");
+    assert_eq!(duration("x = Foo :: bar;"), "x = Foo :: bar;");
+    assert_eq!(duration("ns :: make(1)"), "ns :: make(1)");
+    assert_eq!(duration("ns :: obj.field"), "ns :: obj.field");
+    assert_eq!(duration("2 :: beat => now;"), "2::beat => now;");
+    assert_eq!(duration("s.period :: second"), "s.period::second");
+}
+
+#[test]
+fn duration_after_scientific_and_hex_literals() {
+    assert_eq!(duration("1e3 :: samp"), "1e3::samp");
+    assert_eq!(duration("1.5e-3 ::second"), "1.5e-3::second");
+    assert_eq!(duration("0x10 :: ms"), "0x10::ms");
+    assert_eq!(duration("0xFF:: samp"), "0xFF::samp");
+}
+
+#[test]
+fn ternary_gets_single_spaces() {
+    assert_eq!(ternary("c?a:b;"), "c ? a : b;");
+    assert_eq!(ternary("x  ?  y  :  z => w;"), "x ? y : z => w;");
+    assert_eq!(ternary("a ? b ? c : d : e;"), "a ? b ? c : d : e;");
+    // broken over lines, the layout stays
+    assert_eq!(ternary("c\n    ? a\n    : b;"), "c\n    ? a\n    : b;");
+    // no open `?`: not a ternary
+    assert_eq!(ternary("for (int x:arr) {}"), "for (int x:arr) {}");
+}
+
+#[test]
+fn ternary_next_to_durations() {
+    assert_eq!(
+        ternary("c?1::second:2::second => now;"),
+        "c ? 1::second : 2::second => now;"
+    );
+    let (out, _) = apply_all("fast ?100 ::ms: 1 :: second => now;", &Config::default());
+    assert_eq!(out, "fast ? 100::ms : 1::second => now;");
+    let (out, _) = apply_all("x?y::samp:(n + 1) :: samp => now;", &Config::default());
+    assert_eq!(out, "x ? y::samp : (n + 1)::samp => now;");
+    // the `::` of a duration is never joined with a ternary's `:`
+    assert_eq!(duration("c ? a :::b"), "c ? a :::b");
+}
+
+#[test]
+fn print_brackets_and_commas() {
+    assert_eq!(print_brackets("<<<x>>>;"), "<<< x >>>;");
+    assert_eq!(print_brackets("<< <x>> >;"), "<<< x >>>;");
+    assert_eq!(print_commas("<<< a ,b , c >>>;"), "<<< a, b, c >>>;");
+    assert_eq!(print_commas("<<< f(a ,b) >>>;"), "<<< f(a ,b) >>>;");
+}
+
+#[test]
+fn polar_only_touches_literals() {
+    assert_eq!(polar("% (1, .5) => polar p;"), "%(1, .5) => polar p;");
+    assert_eq!(polar("% ( 1,.5 ) => polar p;"), "%(1, .5) => polar p;");
+    assert_eq!(polar("a % (b);"), "a % (b);");
+    assert_eq!(polar("a %( b ,c);"), "a %( b ,c);");
+}
+
+#[test]
+fn vector_and_complex_literals() {
+    assert_eq!(vector("@ ( 1,2 ,3 ) => vec3 v;"), "@(1, 2, 3) => vec3 v;");
+    assert_eq!(vector("@(f(a ,b),2)"), "@(f(a ,b), 2)");
+    assert_eq!(complex("# ( 1,2 ) => complex c;"), "#(1, 2) => complex c;");
+    assert_eq!(vector(&complex("@( #( 1,2 ),3 )")), "@(#(1, 2), 3)");
+}
+
+#[test]
+fn gruck_leaves_decrement_alone() {
+    assert_eq!(gruck("a-->b;"), "a --> b;");
+    assert_eq!(gruck("if (i-- > 3) {}"), "if (i-- > 3) {}");
+    assert_eq!(ungruck("a -- < b;"), "a --< b;");
+}
+
+#[test]
+fn misc_spacing() {
+    assert_eq!(leading_sign("  - 3.14 => x;"), "  -3.14 => x;");
+    assert_eq!(sporks("spork~go();"), "spork ~ go();");
+    assert_eq!(multiplication("2 *b"), "2 * b");
+}
+
+#[test]
+fn unary_signs_are_glued_mid_line() {
+    assert_eq!(leading_sign("x => - 1.0 => y;"), "x => -1.0 => y;");
+    assert_eq!(leading_sign("f(a, - b, + .5);"), "f(a, -b, +.5);");
+    assert_eq!(leading_sign("return - x;"), "return -x;");
+    assert_eq!(leading_sign("a - - 1 => b;"), "a - -1 => b;");
+    // subtraction stays spaced
+    assert_eq!(leading_sign("a - 1.0 => b;"), "a - 1.0 => b;");
+    assert_eq!(leading_sign("(a) - 1 => b;"), "(a) - 1 => b;");
+    assert_eq!(leading_sign("i-- - 1 => b;"), "i-- - 1 => b;");
+}
+
+#[test]
+fn upchuck_gets_spaces_on_both_sides() {
+    assert_eq!(upchuck("adc =^ fft;"), "adc =^ fft;");
+    assert_eq!(upchuck("adc=^fft;"), "adc =^ fft;");
+    assert_eq!(upchuck("adc\n    =^ fft;"), "adc\n    =^ fft;");
+    // clang-format's take on `x =^ y`: ChucK has no `=` for it to be anything else
+    assert_eq!(upchuck("x = ^y;"), "x =^ y;");
+    // part of a comparison or compound operator, not an upchuck
+    assert_eq!(upchuck("a == ^b;"), "a == ^b;");
+    assert_eq!(upchuck("a ^= ^b;"), "a ^= ^b;");
+}
+
+#[test]
+fn spork_split_across_lines_is_joined() {
+    assert_eq!(sporks("spork\n    ~ foo();"), "spork ~ foo();");
+    assert_eq!(sporks("spork ~\n    foo();"), "spork ~ foo();");
+    assert_eq!(sporks("spork  ~   me.yield();"), "spork ~ me.yield();");
+    assert_eq!(sporks("nospork ~x;"), "nospork ~x;");
+}
+
+#[test]
+fn cast_gets_single_spaces() {
+    assert_eq!(cast("3.0$int => int i;"), "3.0 $ int => int i;");
+    assert_eq!(cast("obj $Foo @=> Foo f;"), "obj $ Foo @=> Foo f;");
+    assert_eq!(cast("(x + 1)$  float"), "(x + 1) $ float");
+}
+
+#[test]
+fn reference_declarations() {
+    assert_eq!(reference("SinOsc@s;"), "SinOsc @ s;");
+    assert_eq!(reference("Foo[] @bar;"), "Foo[] @ bar;");
+    assert_eq!(
+        reference("SinOsc@s;\nnew SinOsc @=> s;\n@(1, 2) => vec2 v;\n"),
+        "SinOsc @ s;\nnew SinOsc @=> s;\n@(1, 2) => vec2 v;\n"
+    );
+}
+
+#[test]
+fn reference_leaves_constructor_destructor_and_operator_names() {
+    let code = "class Foo {\n    fun @construct(int x) {}\n    fun void @destruct() {}\n}\n\
+                fun @operator +(Foo a, Foo b) {}\npublic fun Foo[] @operator -(Foo@a) {}\n";
+    assert_eq!(reference(code), code.replace("(Foo@a)", "(Foo @ a)"));
+}
+
+#[test]
+fn apply_all_reports_fired_transforms() {
+    let (out, fired) = apply_all("s = > dac; <<<x>>>;", &Config::default());
+    assert_eq!(out, "s => dac; <<< x >>>;");
+    assert_eq!(fired, ["chuck_arrow", "print_brackets"]);
+}
+
+#[test]
+fn array_brackets_are_tight() {
+    assert_eq!(array_brackets("float data [ 10 ];"), "float data[10];");
+    assert_eq!(array_brackets("int arr [ ] @=> x;"), "int arr[] @=> x;");
+    assert_eq!(
+        array_brackets("[1, 2] @=> int arr [];"),
+        "[1, 2] @=> int arr[];"
+    );
+    assert_eq!(array_brackets("arr [ i ] [j] => y;"), "arr[i][j] => y;");
+    assert_eq!(array_brackets("float grid [4] [ 4 ];"), "float grid[4][4];");
+    // array literals keep their space after a keyword
+    assert_eq!(array_brackets("return [ 1, 2 ];"), "return [1, 2];");
+    // a literal spread over lines keeps its layout
+    assert_eq!(
+        array_brackets("[\n    1,\n    2\n] @=> int a[];"),
+        "[\n    1,\n    2\n] @=> int a[];"
+    );
+}
+
+#[test]
+fn blank_lines_are_never_added_or_removed() {
+    let blank_runs = |s: &str| s.matches("\n\n\n\n").count();
+    let input = "SinOsc s =\n\n\n\n> dac;\nx\n\n\n\n*y => z;\nspork ~\n\n\n\nfoo();\n<<< a\n\n\n\n>>>;\nc\n\n\n\n-- < d;\n";
+    let (out, _) = apply_all(input, &Config::default());
+    assert_eq!(blank_runs(&out), blank_runs(input), "{out}");
+    assert_eq!(out.lines().count(), input.lines().count(), "{out}");
+}
+
+#[test]
+fn chain_spacing_tight_and_spaced() {
+    let chain = "SinOsc s => LPF f => dac;\n[1, 2] @=> int a[];\nx =<\n    dac;\n";
+    let tight = Spacing {
+        chuck: OperatorSpacing::Tight,
+        ..Spacing::default()
+    };
+    assert_eq!(
+        apply_spacing(chain, &tight),
+        "SinOsc s=>LPF f=>dac;\n[1, 2]@=>int a[];\nx=<\n    dac;\n"
+    );
+    assert_eq!(apply_spacing(chain, &Spacing::default()), chain);
+
+    let config = Config {
+        spacing: tight,
+        ..Config::default()
+    };
+    let (out, fired) = apply_all("a = > b = > c;", &config);
+    assert_eq!(out, "a=>b=>c;");
+    assert!(fired.contains(&"spacing".to_string()));
+    let (out, _) = apply_all("a = > b = > c;", &Config::default());
+    assert_eq!(out, "a => b => c;");
+}
+
+#[test]
+fn other_families_can_be_tight() {
+    let all_tight = Spacing {
+        chuck: OperatorSpacing::Tight,
+        upchuck: OperatorSpacing::Tight,
+        print: OperatorSpacing::Tight,
+        spork: OperatorSpacing::Tight,
+    };
+    assert_eq!(
+        apply_spacing("<<< a, b >>>;\nspork ~ go();\nadc =^ fft;\n", &all_tight),
+        "<<<a, b>>>;\nspork ~go();\nadc=^fft;\n"
+    );
+    let config = Config::parse("[spacing]\nprint = \"tight\"\nspork = \"spaced\"\n").unwrap();
+    assert_eq!(config.spacing.print, OperatorSpacing::Tight);
+    assert_eq!(config.spacing.spork, OperatorSpacing::Spaced);
+    assert!(Config::parse("[spacing]\nprint = \"loose\"\n").is_err());
+    assert!(Config::parse("[spacing]\ncast = \"tight\"\n").is_err());
+}